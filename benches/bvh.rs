@@ -0,0 +1,183 @@
+// Compares the flattened BVH against a recursive tree of boxed nodes, the layout BVH had
+// before it was linearized. Run with `cargo bench --bench bvh`.
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use ray_tracing_in_one_weekend::*;
+
+const RAYS: usize = 200_000;
+const RUNS: usize = 5;
+
+// Median split on the widest axis, left child always visited first
+struct TreeNode {
+    left: Box<dyn Hitable>,
+    right: Box<dyn Hitable>,
+    bbox: AABB,
+}
+
+impl TreeNode {
+    fn build(mut objects: Vec<Box<dyn Hitable>>) -> Box<dyn Hitable> {
+        if objects.len() == 1 {
+            return objects.pop().unwrap();
+        }
+
+        let bbox = objects.bounding_box(0.0, 1.0).unwrap();
+        let extent = bbox.max() - bbox.min();
+        let axis = (0..3)
+            .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+            .unwrap();
+        let centroid = |o: &dyn Hitable| o.bounding_box(0.0, 1.0).unwrap().centroid()[axis];
+        objects.sort_by(|a, b| centroid(a.as_ref()).total_cmp(&centroid(b.as_ref())));
+
+        let right = objects.split_off(objects.len() / 2);
+        Box::new(TreeNode {
+            left: TreeNode::build(objects),
+            right: TreeNode::build(right),
+            bbox,
+        })
+    }
+}
+
+impl Hitable for TreeNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+        let left_hit_rec = self.left.hit(r, t_min, t_max, rng);
+        let t_max = left_hit_rec.as_ref().map_or(t_max, |rec| rec.t);
+        self.right.hit(r, t_min, t_max, rng).or(left_hit_rec)
+    }
+    fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
+        Some(self.bbox)
+    }
+}
+
+type SceneBuilder = fn(&mut StdRng) -> World;
+
+fn material() -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(SolidColor::from_rbg(0.73, 0.73, 0.73)))
+}
+
+// The cluster of spheres from `ray_tracing_the_next_week`
+fn spheres(rng: &mut StdRng) -> World {
+    let mat = material();
+    (0..1000)
+        .map(|_| {
+            let center = Point3::new(
+                rng.gen_range(0.0..165.0),
+                rng.gen_range(0.0..165.0),
+                rng.gen_range(0.0..165.0),
+            );
+            Box::new(Sphere::new(center, 10.0, mat.clone())) as Box<dyn Hitable>
+        })
+        .collect()
+}
+
+// The ground of `ray_tracing_the_next_week`
+fn boxes(rng: &mut StdRng) -> World {
+    let mat = material();
+    let mut world = World::new();
+    for i in 0..20 {
+        for j in 0..20 {
+            let p0 = Point3::new(-1000.0 + i as f64 * 100.0, 0.0, -1000.0 + j as f64 * 100.0);
+            let p1 = p0 + Vec3::new(100.0, rng.gen_range(1.0..101.0), 100.0);
+            world.push(Box::new(Cube::new(p0, p1, mat.clone())));
+        }
+    }
+    world
+}
+
+// A finely tessellated unit sphere
+fn triangles() -> World {
+    let mat = material();
+    let (rings, segments) = (128, 256);
+    let vertex = |i: usize, j: usize| {
+        let theta = std::f64::consts::PI * i as f64 / rings as f64;
+        let phi = 2.0 * std::f64::consts::PI * j as f64 / segments as f64;
+        Point3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    };
+
+    let mut world = World::new();
+    for i in 0..rings {
+        for j in 0..segments {
+            let (a, b) = (vertex(i, j), vertex(i + 1, j));
+            let (c, d) = (vertex(i + 1, j + 1), vertex(i, j + 1));
+            world.push(Box::new(Triangle::new(a, b, c, mat.clone())));
+            world.push(Box::new(Triangle::new(a, c, d, mat.clone())));
+        }
+    }
+    world
+}
+
+// Rays from around the scene towards random points inside its bounds
+fn rays(bbox: &AABB, rng: &mut StdRng) -> Vec<Ray> {
+    let center = bbox.centroid();
+    let radius = (bbox.max() - bbox.min()).length();
+    (0..RAYS)
+        .map(|_| {
+            let origin = center + radius * Vec3::random_in_unit_sphere(rng).normalized();
+            let target = Point3::new(
+                rng.gen_range(bbox.min().x()..bbox.max().x()),
+                rng.gen_range(bbox.min().y()..bbox.max().y()),
+                rng.gen_range(bbox.min().z()..bbox.max().z()),
+            );
+            Ray::new(origin, target - origin, 0.0)
+        })
+        .collect()
+}
+
+// Fastest of several runs and the number of hits, which has to match between layouts
+fn time(hitable: &dyn Hitable, rays: &[Ray]) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut hits = 0;
+    let mut rng = Pcg32::new(0, 0);
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        hits = rays
+            .iter()
+            .filter(|r| hitable.hit(r, 0.001, f64::INFINITY, &mut rng).is_some())
+            .count();
+        best = best.min(start.elapsed());
+    }
+    (best, hits)
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let scenes: [(&str, SceneBuilder); 3] = [
+        ("spheres", spheres),
+        ("boxes", boxes),
+        ("triangles", |_| triangles()),
+    ];
+
+    println!(
+        "{:<12}{:>10}{:>14}{:>14}{:>10}",
+        "scene", "objects", "tree Mray/s", "flat Mray/s", "speedup"
+    );
+    for (name, build) in scenes {
+        let objects = build(&mut rng).len();
+        let tree = TreeNode::build(build(&mut StdRng::seed_from_u64(1)));
+        let flat = BVH::new(build(&mut StdRng::seed_from_u64(1)), 0.0, 1.0);
+        let rays = rays(&flat.bounding_box(0.0, 1.0).unwrap(), &mut rng);
+
+        let (tree_time, tree_hits) = time(tree.as_ref(), &rays);
+        let (flat_time, flat_hits) = time(&flat, &rays);
+        assert_eq!(tree_hits, flat_hits, "layouts disagree on `{}`", name);
+
+        let mrays = |t: Duration| RAYS as f64 / t.as_secs_f64() / 1.0e6;
+        println!(
+            "{:<12}{:>10}{:>14.2}{:>14.2}{:>9.2}x",
+            name,
+            objects,
+            mrays(tree_time),
+            mrays(flat_time),
+            tree_time.as_secs_f64() / flat_time.as_secs_f64()
+        );
+    }
+}
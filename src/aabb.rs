@@ -0,0 +1,71 @@
+use crate::{Point3, Vec3};
+
+#[derive(Clone, Copy)]
+pub struct AABB {
+    min: Point3,
+    max: Point3,
+}
+
+impl AABB {
+    pub fn new(min: Point3, max: Point3) -> AABB {
+        AABB { min, max }
+    }
+    pub fn surrounding_box(box0: &AABB, box1: &AABB) -> AABB {
+        let small = Point3::new(
+            box0.min().x().min(box1.min().x()),
+            box0.min().y().min(box1.min().y()),
+            box0.min().z().min(box1.min().z()),
+        );
+        let big = Point3::new(
+            box0.max().x().max(box1.max().x()),
+            box0.max().y().max(box1.max().y()),
+            box0.max().z().max(box1.max().z()),
+        );
+        AABB {
+            min: small,
+            max: big,
+        }
+    }
+    pub fn min(&self) -> Point3 {
+        self.min
+    }
+    pub fn max(&self) -> Point3 {
+        self.max
+    }
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+    pub fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> bool {
+        let d = r.direction();
+        let inv_dir = Vec3::new(1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z());
+        self.hit_with_inv_dir(r.origin(), inv_dir, t_min, t_max)
+    }
+    // Slab test with the inverse ray direction computed once by the caller, the intervals of
+    // all three axes have to overlap
+    pub fn hit_with_inv_dir(
+        &self,
+        origin: Point3,
+        inv_dir: Vec3,
+        mut t_min: f64,
+        mut t_max: f64,
+    ) -> bool {
+        for a in 0..3 {
+            let t0 = (self.min[a] - origin[a]) * inv_dir[a];
+            let t1 = (self.max[a] - origin[a]) * inv_dir[a];
+            let (t0, t1) = if inv_dir[a] < 0.0 { (t1, t0) } else { (t0, t1) };
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+
+            if t_max <= t_min {
+                return false;
+            }
+        }
+
+        true
+    }
+}
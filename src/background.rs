@@ -0,0 +1,248 @@
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use image::codecs::hdr::HdrDecoder;
+use image::ImageResult;
+
+use crate::tonemap::luminance;
+use crate::{Color, ImageFormat, Sampler, Vec3};
+
+// Light coming from infinitely far away, seen by the rays that leave the scene
+pub trait Background: Send + Sync {
+    // Radiance arriving from `direction`, which doesn't have to be normalized
+    fn color(&self, direction: Vec3) -> Color;
+    // Whether the renderer should sample it directly, like the emissive objects
+    fn is_light(&self) -> bool {
+        false
+    }
+    // Solid angle density of `random` picking `direction`
+    fn pdf_value(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+    fn random(&self, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(0.0, 1.0, 0.0)
+    }
+}
+
+pub struct ConstantBackground {
+    color: Color,
+}
+
+impl ConstantBackground {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Background for ConstantBackground {
+    fn color(&self, _: Vec3) -> Color {
+        self.color
+    }
+}
+
+// Blend from `bottom` straight down to `top` straight up, by default the sky of the book
+pub struct SkyGradient {
+    bottom: Color,
+    top: Color,
+}
+
+impl SkyGradient {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+    pub fn bottom(&self) -> Color {
+        self.bottom
+    }
+    pub fn top(&self) -> Color {
+        self.top
+    }
+}
+
+impl Default for SkyGradient {
+    fn default() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Background for SkyGradient {
+    fn color(&self, direction: Vec3) -> Color {
+        let t = 0.5 * (direction.normalized().y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+// Equirectangular image around the scene with +y up, the center of the image looks down -z.
+// Directions are sampled in proportion to the brightness of the pixels, so small bright
+// features like the sun are found by light sampling instead of only by chance
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // Multiplies the pixels
+    intensity: f64,
+    // Degrees the map is turned around the y axis
+    rotation: f64,
+    // Probability of picking each pixel, all 0 for a black image
+    pixel_pdf: Vec<f64>,
+    total_weight: f64,
+    // Cumulative distribution of the rows, and of the pixels within each row
+    row_cdf: Vec<f64>,
+    column_cdf: Vec<f64>,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        // Rows near the poles cover less solid angle
+        let weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(index, &pixel)| {
+                let theta = PI * ((index / width) as f64 + 0.5) / height as f64;
+                luminance(pixel).max(0.0) * theta.sin()
+            })
+            .collect();
+        let total: f64 = weights.iter().sum();
+
+        let mut row_weights = Vec::with_capacity(height);
+        let mut column_cdf = Vec::with_capacity(height * (width + 1));
+        for row in weights.chunks(width) {
+            row_weights.push(row.iter().sum());
+            column_cdf.extend(cdf(row));
+        }
+        let pixel_pdf = weights
+            .iter()
+            .map(|&weight| if total > 0.0 { weight / total } else { 0.0 })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+            intensity: 1.0,
+            rotation: 0.0,
+            pixel_pdf,
+            total_weight: total,
+            row_cdf: cdf(&row_weights),
+            column_cdf,
+        }
+    }
+
+    // .hdr and .exr files hold linear colors, 8 bit formats are read as linear as well
+    pub fn open(file: impl AsRef<Path>) -> ImageResult<Self> {
+        let file = file.as_ref();
+        // `image::open` turns .hdr files into 8 bit colors with a gamma applied
+        let (w, h, pixels) = if ImageFormat::from_path(file) == Some(ImageFormat::Hdr) {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(file)?))?;
+            let metadata = decoder.metadata();
+            let pixels = decoder.read_image_hdr()?;
+            (metadata.width, metadata.height, pixels)
+        } else {
+            let img = image::open(file)?.into_rgb32f();
+            let (w, h) = img.dimensions();
+            (w, h, img.pixels().copied().collect())
+        };
+        let pixels = pixels
+            .iter()
+            .map(|p| Color::new(p[0] as f64, p[1] as f64, p[2] as f64))
+            .collect();
+
+        Ok(Self::new(w as usize, h as usize, pixels))
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+    pub fn with_rotation(mut self, degrees: f64) -> Self {
+        self.rotation = degrees;
+        self
+    }
+
+    // Image coordinates in [0, 1) of `direction`, v counted from the top
+    fn uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.normalized();
+        let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI) - self.rotation / 360.0;
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+
+        (u.rem_euclid(1.0), v)
+    }
+    fn pixel_index(&self, u: f64, v: f64) -> usize {
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        y * self.width + x
+    }
+}
+
+impl Background for EnvironmentMap {
+    fn color(&self, direction: Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        self.intensity * self.pixels[self.pixel_index(u, v)]
+    }
+    fn is_light(&self) -> bool {
+        self.intensity > 0.0 && self.total_weight > 0.0
+    }
+    // The pixels are picked with `pixel_pdf` and sampled uniformly in u and v, converted from
+    // the area of the image to solid angle
+    fn pdf_value(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let pdf_uv = self.pixel_pdf[self.pixel_index(u, v)] * (self.width * self.height) as f64;
+
+        pdf_uv / (2.0 * PI * PI * sin_theta)
+    }
+    fn random(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (r1, r2) = sampler.next_2d();
+        let (y, dv) = sample_cdf(&self.row_cdf, r1);
+        let row = &self.column_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let (x, du) = sample_cdf(row, r2);
+
+        let u = (x as f64 + du) / self.width as f64 + self.rotation / 360.0;
+        let v = (y as f64 + dv) / self.height as f64;
+        let theta = PI * v;
+        let phi = 2.0 * PI * (u - 0.5);
+
+        Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+}
+
+// Normalized running sums starting at 0, uniform if all weights are 0
+fn cdf(weights: &[f64]) -> Vec<f64> {
+    let total: f64 = weights.iter().sum();
+    let n = weights.len();
+    let mut cdf = Vec::with_capacity(n + 1);
+    let mut sum = 0.0;
+    cdf.push(0.0);
+    for (i, &weight) in weights.iter().enumerate() {
+        sum += weight;
+        cdf.push(if total > 0.0 {
+            sum / total
+        } else {
+            (i + 1) as f64 / n as f64
+        });
+    }
+
+    cdf
+}
+
+// Bucket of `cdf` that `u` falls in, and where in the bucket as a fraction
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let index = cdf.partition_point(|&c| c <= u).clamp(1, cdf.len() - 1) - 1;
+    let width = cdf[index + 1] - cdf[index];
+    let fraction = if width > 0.0 {
+        ((u - cdf[index]) / width).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    (index, fraction)
+}
@@ -1,114 +1,381 @@
-use crate::{Hitable, AABB};
-use rand::Rng;
-use std::cmp::Ordering;
-
-enum BVHNode {
-    Branch {
-        left: Box<dyn Hitable>,
-        right: Box<dyn Hitable>,
-    },
-    Leaf(Box<dyn Hitable>),
+use rand::RngCore;
+
+use crate::{HitRecord, Hitable, Point3, Ray, Vec3, AABB};
+
+// Relative costs of visiting a node and intersecting a primitive for the surface area heuristic
+const TRAVERSAL_COST: f64 = 0.125;
+const INTERSECTION_COST: f64 = 1.0;
+
+// Below this depth nodes are split in half by count instead of by cost, so that even
+// degenerate inputs fit in the fixed size traversal stack
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+pub struct BVHOptions {
+    // Nodes with more primitives are always split
+    pub max_leaf_size: usize,
+    // Number of buckets the centroids are sorted into along each axis to evaluate splits
+    pub bins: usize,
+}
+
+impl Default for BVHOptions {
+    fn default() -> Self {
+        Self {
+            max_leaf_size: 4,
+            bins: 16,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BVHStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub primitive_count: usize,
+    pub max_depth: usize,
+    // Expected cost of a random ray hitting the root, in units of primitive intersections
+    pub sah_cost: f64,
 }
 
+// Nodes are stored depth first, so the first child of an interior node directly follows it
+struct LinearNode {
+    bbox: AABB,
+    // Leaf: index of the first primitive, interior: index of the second child
+    offset: usize,
+    // Number of primitives, 0 for interior nodes
+    count: usize,
+    // Split axis of interior nodes, used to visit the nearer child first
+    axis: usize,
+}
+
+// Deterministic: the same primitives in the same order always give the same tree
 pub struct BVH {
-    tree: BVHNode,
+    nodes: Vec<LinearNode>,
+    // Ordered so the primitives of each leaf are contiguous
+    primitives: Vec<Box<dyn Hitable>>,
+    stats: BVHStats,
+}
+
+struct Primitive {
+    object: Box<dyn Hitable>,
     bbox: AABB,
+    centroid: Point3,
 }
-fn box_compare(
-    time0: f64,
-    time1: f64,
-    axis: usize,
-) -> impl FnMut(&Box<dyn Hitable>, &Box<dyn Hitable>) -> Ordering {
-    move |a, b| {
-        let a_bbox = a.bounding_box(time0, time1);
-        let b_bbox = b.bounding_box(time0, time1);
-        if let (Some(a), Some(b)) = (a_bbox, b_bbox) {
-            let ac = a.min()[axis] + a.max()[axis];
-            let bc = b.min()[axis] + b.max()[axis];
-            ac.partial_cmp(&bc).unwrap()
-        } else {
-            panic!["no bounding box in bvh node"]
+
+#[derive(Clone, Copy)]
+struct Bin {
+    count: usize,
+    bbox: Option<AABB>,
+}
+
+fn union(a: Option<AABB>, b: &AABB) -> AABB {
+    match a {
+        Some(a) => AABB::surrounding_box(&a, b),
+        None => *b,
+    }
+}
+
+fn bin_index(value: f64, min: f64, extent: f64, bins: usize) -> usize {
+    (((value - min) / extent * bins as f64) as usize).min(bins - 1)
+}
+
+// Lowest cost split over all axes as (cost, axis, first bin of the right side), where the
+// cost is the sum of the child areas weighted by their primitive counts
+fn best_split(
+    primitives: &[Primitive],
+    centroid_bounds: &AABB,
+    bins: usize,
+) -> Option<(f64, usize, usize)> {
+    let mut best: Option<(f64, usize, usize)> = None;
+    for axis in 0..3 {
+        let min = centroid_bounds.min()[axis];
+        let extent = centroid_bounds.max()[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut buckets = vec![
+            Bin {
+                count: 0,
+                bbox: None
+            };
+            bins
+        ];
+        for p in primitives {
+            let b = bin_index(p.centroid[axis], min, extent, bins);
+            buckets[b].count += 1;
+            buckets[b].bbox = Some(union(buckets[b].bbox, &p.bbox));
+        }
+
+        // Sweep from the right to get the area and count of every right side, then from the
+        // left evaluating each split
+        let mut right_area = vec![0.0; bins];
+        let mut right_count = vec![0; bins];
+        let mut acc_bbox = None;
+        let mut acc_count = 0;
+        for b in (1..bins).rev() {
+            if let Some(bin_bbox) = &buckets[b].bbox {
+                acc_bbox = Some(union(acc_bbox, bin_bbox));
+            }
+            acc_count += buckets[b].count;
+            right_area[b] = acc_bbox.map_or(0.0, |bbox| bbox.surface_area());
+            right_count[b] = acc_count;
+        }
+
+        let mut acc_bbox = None;
+        let mut acc_count = 0;
+        for split in 1..bins {
+            if let Some(bin_bbox) = &buckets[split - 1].bbox {
+                acc_bbox = Some(union(acc_bbox, bin_bbox));
+            }
+            acc_count += buckets[split - 1].count;
+            if acc_count == 0 || right_count[split] == 0 {
+                continue;
+            }
+
+            let left_area = acc_bbox.map_or(0.0, |bbox| bbox.surface_area());
+            let cost = left_area * acc_count as f64 + right_area[split] * right_count[split] as f64;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, split));
+            }
         }
     }
+
+    best
 }
-// axis ranges to get best BVH
-impl BVH {
-    pub fn new(mut hitables: Vec<Box<dyn Hitable>>, time0: f64, time1: f64) -> Self {
-        // eprintln!("{}", hitables.len());
-        match hitables.len() {
-            0 => panic!("[BHV::new] No objects in the scene"),
-            1 => {
-                let leaf = hitables.pop().unwrap();
-                if let Some(bbox) = leaf.bounding_box(time0, time1) {
-                    BVH {
-                        tree: BVHNode::Leaf(leaf),
-                        bbox,
-                    }
-                } else {
-                    panic!("no bounding box in bvh node")
-                }
+
+struct Builder {
+    options: BVHOptions,
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Box<dyn Hitable>>,
+}
+
+impl Builder {
+    // Appends the subtree for `primitives` and returns the index of its root
+    fn build(&mut self, mut primitives: Vec<Primitive>, depth: usize) -> usize {
+        let bbox = primitives
+            .iter()
+            .fold(None, |acc, p| Some(union(acc, &p.bbox)))
+            .expect("[BVH::new] No objects in the node");
+        let n = primitives.len();
+        let index = self.nodes.len();
+        self.nodes.push(LinearNode {
+            bbox,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        let (left, right, axis) = match self.split(&mut primitives, &bbox, depth) {
+            Some((right, axis)) => (primitives, right, axis),
+            None => {
+                self.nodes[index].offset = self.primitives.len();
+                self.nodes[index].count = n;
+                self.primitives
+                    .extend(primitives.into_iter().map(|p| p.object));
+                return index;
             }
-            _ => {
-                let mut rng = rand::thread_rng();
-                let axis = rng.gen_range(0..2);
+        };
 
-                hitables.sort_unstable_by(box_compare(time0, time1, axis));
+        self.build(left, depth + 1);
+        let second = self.build(right, depth + 1);
+        self.nodes[index].offset = second;
+        self.nodes[index].axis = axis;
 
-                let mid = hitables.len() / 2;
-                // if mid != 0 {
-                // let left = BVH::new(hitables.drain(0..mid).collect(), time0, time1);
-                // let right = BVH::new(hitables.drain(mid..hitables.len()).collect(), time0, time1);
-                let right = BVH::new(hitables.drain(mid..).collect(), time0, time1);
-                let left = BVH::new(hitables, time0, time1);
-                // }
+        index
+    }
 
-                let left_bbox = left
-                    .bounding_box(time0, time1)
-                    .expect("no bounding box in left bvh node");
+    // Splits off the primitives of the right child and returns them with the split axis, or
+    // None if the node should become a leaf
+    fn split(
+        &self,
+        primitives: &mut Vec<Primitive>,
+        bbox: &AABB,
+        depth: usize,
+    ) -> Option<(Vec<Primitive>, usize)> {
+        let n = primitives.len();
+        if n == 1 {
+            return None;
+        }
 
-                let right_bbox = right
-                    .bounding_box(time0, time1)
-                    .expect("no bounding box in right bvh node");
+        let centroid_bounds = primitives
+            .iter()
+            .fold(None, |acc, p| {
+                Some(union(acc, &AABB::new(p.centroid, p.centroid)))
+            })
+            .unwrap();
+        let extent = centroid_bounds.max() - centroid_bounds.min();
+        let widest_axis = (0..3)
+            .max_by(|&a, &b| extent[a].total_cmp(&extent[b]))
+            .unwrap();
 
-                let bbox = AABB::surrounding_box(&left_bbox, &right_bbox);
+        let bins = self.options.bins.max(2);
+        let best = if depth < MAX_SAH_DEPTH {
+            best_split(primitives, &centroid_bounds, bins)
+        } else {
+            None
+        };
 
-                BVH {
-                    tree: BVHNode::Branch {
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    },
-                    bbox,
+        match best {
+            Some((cost, axis, split)) => {
+                let area = bbox.surface_area();
+                let split_cost = TRAVERSAL_COST
+                    + if area > 0.0 {
+                        INTERSECTION_COST * cost / area
+                    } else {
+                        INTERSECTION_COST * n as f64
+                    };
+                if n <= self.options.max_leaf_size && INTERSECTION_COST * n as f64 <= split_cost {
+                    return None;
                 }
+
+                let min = centroid_bounds.min()[axis];
+                let (left, right) = std::mem::take(primitives)
+                    .into_iter()
+                    .partition(|p| bin_index(p.centroid[axis], min, extent[axis], bins) < split);
+                *primitives = left;
+                Some((right, axis))
+            }
+            // All centroids coincide or the tree is already too deep, nothing to gain from
+            // splitting apart from smaller leaves
+            None => {
+                if n <= self.options.max_leaf_size {
+                    return None;
+                }
+                primitives
+                    .sort_by(|a, b| a.centroid[widest_axis].total_cmp(&b.centroid[widest_axis]));
+                Some((primitives.split_off(n / 2), widest_axis))
             }
         }
     }
 }
 
-impl Hitable for BVH {
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<crate::HitRecord> {
-        if !self.bbox.hit(r, t_min, t_max) {
-            return None;
+impl BVH {
+    pub fn new(hitables: Vec<Box<dyn Hitable>>, time0: f64, time1: f64) -> Self {
+        Self::with_options(hitables, time0, time1, BVHOptions::default())
+    }
+
+    // Binned surface area heuristic build
+    pub fn with_options(
+        hitables: Vec<Box<dyn Hitable>>,
+        time0: f64,
+        time1: f64,
+        options: BVHOptions,
+    ) -> Self {
+        if hitables.is_empty() {
+            panic!("[BHV::new] No objects in the scene");
+        }
+
+        let primitives: Vec<Primitive> = hitables
+            .into_iter()
+            .map(|object| {
+                let bbox = object
+                    .bounding_box(time0, time1)
+                    .expect("no bounding box in bvh node");
+                Primitive {
+                    centroid: bbox.centroid(),
+                    object,
+                    bbox,
+                }
+            })
+            .collect();
+
+        let mut builder = Builder {
+            options,
+            nodes: Vec::with_capacity(2 * primitives.len()),
+            primitives: Vec::with_capacity(primitives.len()),
         };
+        builder.build(primitives, 0);
 
-        match &self.tree {
-            BVHNode::Branch { left, right } => {
-                let left_hit_rec = left.hit(r, t_min, t_max);
-                let t_max = if left_hit_rec.is_some() {
-                    left_hit_rec.as_ref().unwrap().t
-                } else {
-                    t_max
-                };
-                let right_hit_rec = right.hit(r, t_min, t_max);
-                if left_hit_rec.is_some() {
-                    right_hit_rec
+        let mut bvh = BVH {
+            nodes: builder.nodes,
+            primitives: builder.primitives,
+            stats: BVHStats::default(),
+        };
+        let mut stats = BVHStats::default();
+        bvh.collect_stats(0, 0, &mut stats);
+        bvh.stats = stats;
+
+        bvh
+    }
+
+    pub fn stats(&self) -> BVHStats {
+        self.stats
+    }
+
+    fn collect_stats(&self, index: usize, depth: usize, stats: &mut BVHStats) {
+        let node = &self.nodes[index];
+        let root_area = self.nodes[0].bbox.surface_area();
+        let area_ratio = if root_area > 0.0 {
+            node.bbox.surface_area() / root_area
+        } else {
+            1.0
+        };
+        stats.node_count += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+
+        if node.count > 0 {
+            stats.leaf_count += 1;
+            stats.primitive_count += node.count;
+            stats.sah_cost += INTERSECTION_COST * node.count as f64 * area_ratio;
+        } else {
+            stats.sah_cost += TRAVERSAL_COST * area_ratio;
+            self.collect_stats(index + 1, depth + 1, stats);
+            self.collect_stats(node.offset, depth + 1, stats);
+        }
+    }
+}
+
+impl Hitable for BVH {
+    // Iterative front to back traversal, the child on the side the ray comes from is visited
+    // first so the second one can often be culled by the closer hit
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let d = r.direction();
+        let inv_dir = Vec3::new(1.0 / d.x(), 1.0 / d.y(), 1.0 / d.z());
+        let dir_is_neg = [inv_dir.x() < 0.0, inv_dir.y() < 0.0, inv_dir.z() < 0.0];
+
+        let mut closest = None;
+        let mut closest_so_far = t_max;
+        let mut stack = [0; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            if node
+                .bbox
+                .hit_with_inv_dir(r.origin(), inv_dir, t_min, closest_so_far)
+            {
+                if node.count > 0 {
+                    for object in &self.primitives[node.offset..node.offset + node.count] {
+                        if let Some(rec) = object.hit(r, t_min, closest_so_far, rng) {
+                            closest_so_far = rec.t;
+                            closest = Some(rec);
+                        }
+                    }
                 } else {
-                    left_hit_rec
+                    let (near, far) = if dir_is_neg[node.axis] {
+                        (node.offset, index + 1)
+                    } else {
+                        (index + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    index = near;
+                    continue;
                 }
             }
-            BVHNode::Leaf(leaf) => leaf.hit(r, t_min, t_max),
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len];
         }
+
+        closest
     }
     fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
-        Some(self.bbox)
+        Some(self.nodes[0].bbox)
     }
 }
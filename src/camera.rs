@@ -1,6 +1,4 @@
-use rand::Rng;
-
-use crate::{CameraConfig, Point3, Ray, Vec3};
+use crate::{CameraConfig, Point3, Ray, Sampler, Vec3};
 
 pub struct Camera {
     origin: Point3,
@@ -34,23 +32,20 @@ impl Camera {
             horizontal: h,
             vertical: v,
             lower_left_corner: llc,
-            cu: cu,
-            cv: cv,
+            cu,
+            cv,
             lens_radius: camera_config.aperture / 2.0,
             time0: camera_config.time0,
             time1: camera_config.time1,
         }
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
-        let rd = self.lens_radius * Vec3::random_in_unit_disk();
+    // Always takes the lens and time dimensions from `sampler`, even for a pinhole camera
+    // without motion blur, so the following dimensions line up between scenes
+    pub fn get_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * Vec3::concentric_disk(sampler.next_2d());
         let offset = self.cu * rd.x() + self.cv * rd.y();
-
-        let mut time = self.time0;
-        if self.time1 - self.time0 > f64::EPSILON {
-            let mut rng = rand::thread_rng();
-            time = rng.gen_range(self.time0..self.time1);
-        }
+        let time = self.time0 + sampler.next_1d() * (self.time1 - self.time0);
 
         Ray::new(
             self.origin + offset,
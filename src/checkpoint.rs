@@ -0,0 +1,153 @@
+use std::fs::{self, File};
+use std::hash::Hasher;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::{Film, Renderer};
+
+const MAGIC: &[u8; 8] = b"RTCHKPT1";
+
+// Accumulated samples of a render, saved between passes so a stopped render can go on
+pub struct Checkpoint {
+    // `Renderer::scene_hash` of the render it belongs to
+    pub scene_hash: u64,
+    // Samples per pixel rendered so far, pixels stopped by adaptive sampling can have fewer
+    pub samples: u64,
+    // Samples per pixel the sampler spreads its sequence over, those of the first run. Kept
+    // when resuming with more so the new samples continue it instead of repeating its points
+    pub sequence_length: u64,
+    pub film: Film,
+}
+
+impl Checkpoint {
+    // Nothing rendered yet
+    pub fn new(renderer: &Renderer) -> Self {
+        let config = renderer.config();
+        Self {
+            scene_hash: renderer.scene_hash(),
+            samples: 0,
+            sequence_length: config.samples_per_pixel,
+            film: Film::new(config.image_width, config.image_height, config.filter),
+        }
+    }
+
+    // Written next to `path` and renamed over it, so a render killed while saving keeps the
+    // previous checkpoint
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut out = BufWriter::new(File::create(&tmp)?);
+        out.write_all(MAGIC)?;
+        out.write_all(&self.scene_hash.to_le_bytes())?;
+        out.write_all(&self.samples.to_le_bytes())?;
+        out.write_all(&self.sequence_length.to_le_bytes())?;
+        self.film.write_to(&mut out)?;
+        out.into_inner()?.sync_all()?;
+
+        fs::rename(&tmp, path)
+    }
+
+    // Fails if the checkpoint was saved for another scene or with other settings
+    pub fn load(path: impl AsRef<Path>, renderer: &Renderer) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+        let mut input = BufReader::new(File::open(path)?);
+        let read_u64 = |input: &mut BufReader<File>| -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let scene_hash = read_u64(&mut input)?;
+        if scene_hash != renderer.scene_hash() {
+            return Err(invalid(
+                "the checkpoint was saved for a different scene or different settings",
+            ));
+        }
+        let samples = read_u64(&mut input)?;
+        let sequence_length = read_u64(&mut input)?;
+
+        let config = renderer.config();
+        let film = Film::read_from(
+            &mut input,
+            config.image_width,
+            config.image_height,
+            config.filter,
+        )?;
+
+        Ok(Self {
+            scene_hash,
+            samples,
+            sequence_length,
+            film,
+        })
+    }
+}
+
+// 64 bit FNV-1a, unlike `DefaultHasher` it's the same on every Rust version so checkpoints
+// stay valid
+pub(crate) struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{two_checkered_spheres, Backend};
+
+    fn small_renderer(seed: u64) -> Renderer {
+        let (mut config, world, camera) = two_checkered_spheres();
+        config.image_width = 16;
+        config.image_height = 9;
+        config.samples_per_pixel = 4;
+        config.seed = seed;
+        Renderer::new(config, world, camera)
+    }
+
+    #[test]
+    fn loads_only_for_the_same_scene() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.bin", std::process::id()));
+        let renderer = small_renderer(1);
+        let mut checkpoint = Checkpoint::new(&renderer);
+        renderer.render_passes(Backend::Sequential, &(), &mut checkpoint, 2, &mut |_| {});
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path, &renderer).unwrap();
+        assert_eq!(loaded.samples, 4);
+        assert_eq!(loaded.sequence_length, 4);
+        let mut saved = Vec::new();
+        let mut read = Vec::new();
+        checkpoint.film.write_to(&mut saved).unwrap();
+        loaded.film.write_to(&mut read).unwrap();
+        assert_eq!(read, saved);
+
+        let other_seed = Checkpoint::load(&path, &small_renderer(2));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            other_seed.err().map(|err| err.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+}
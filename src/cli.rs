@@ -0,0 +1,386 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use ray_tracing_in_one_weekend::{
+    AdaptiveSampling, Backend, Camera, Color, Config, ConstantBackground, EnvironmentMap, Filter,
+    FilterKind, ImageFormat, SamplerKind, Scene, SkyGradient, ToneMapper, Vec3,
+};
+
+pub const USAGE: &str = "\
+Usage: ray_tracing_in_one_weekend [OPTIONS] [SCENE]
+
+Renders one of the prebuilt scenes (default: ray_tracing_the_next_week)
+or a scene description file ending in .toml.
+
+Options:
+  -o, --output <PATH>              Output file, `-` for P3 on stdout (default: -)
+                                   The format is picked from the extension:
+                                   .png, .jpg/.jpeg, .ppm (binary P6), .hdr, .exr
+      --backend <BACKEND>          `rayon` or `sequential` (default: rayon)
+      --sample-heatmap <PATH>      Also write the number of samples taken per pixel
+                                   as an image
+      --checkpoint <PATH>          Render in passes and save the accumulated samples to
+                                   PATH, at most every --checkpoint-interval
+      --checkpoint-interval <SECS> Seconds between checkpoints (default: 60)
+      --pass-samples <N>           Samples per pixel in a pass (default: 16 with
+                                   --checkpoint, all of them without)
+      --resume                     Continue from the --checkpoint file, add samples by
+                                   raising --samples-per-pixel
+  -l, --list                       List the available scenes and exit
+  -h, --help                       Print this help and exit
+
+Config overrides:
+      --image-width <N>
+      --samples-per-pixel <N>
+      --max-depth <N>
+      --roulette-depth <N>         Bounces before Russian roulette may end a path
+                                   (default: 3)
+      --background-color <R,G,B>
+      --background <BACKGROUND>    `sky` for the gradient of the book, or an .hdr/.exr
+                                   environment map around the scene
+      --tile-size <N>
+      --seed <N>                   Same seed, same image (default: 0)
+      --sampler <SAMPLER>          `random`, `stratified`, `halton`, `sobol` or
+                                   `blue_noise` (default: sobol)
+      --filter <FILTER>            `box`, `tent`, `gaussian`, `mitchell` or `lanczos`
+                                   (default: box)
+      --filter-radius <PIXELS>     Reach of the filter (default: depends on the filter)
+      --tone-mapper <OPERATOR>     `clamp`, `reinhard`, `aces` or `agx` for 8 bit output
+                                   (default: clamp)
+      --exposure <STOPS>           Brightness change before tone mapping (default: 0)
+      --adaptive-threshold <F>     Stop sampling a pixel once the standard error of its
+                                   mean is below F times the mean, e.g. 0.01
+      --min-samples <N>            Samples per pixel before adaptive sampling may stop
+                                   (default: 16)
+
+Camera overrides:
+      --lookfrom <X,Y,Z>
+      --lookat <X,Y,Z>
+      --vup <X,Y,Z>
+      --vfov <DEGREES>
+      --aperture <F>
+      --dist-to-focus <F>
+      --time0 <F>
+      --time1 <F>
+";
+
+pub enum Command {
+    Help,
+    List,
+    Render(Box<Args>),
+}
+
+pub struct Args {
+    pub scene: String,
+    pub output: String,
+    pub sample_heatmap: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Duration,
+    pub pass_samples: Option<u64>,
+    pub resume: bool,
+    pub backend: Backend,
+    pub overrides: Overrides,
+}
+
+#[derive(Default)]
+pub struct Overrides {
+    pub image_width: Option<u64>,
+    pub samples_per_pixel: Option<u64>,
+    pub max_depth: Option<u64>,
+    pub roulette_depth: Option<u64>,
+    pub background_color: Option<Color>,
+    // `sky` or the path of an environment map
+    pub background: Option<String>,
+    pub tile_size: Option<u64>,
+    pub seed: Option<u64>,
+    pub sampler: Option<SamplerKind>,
+    pub filter: Option<FilterKind>,
+    pub filter_radius: Option<f64>,
+    pub tone_mapper: Option<ToneMapper>,
+    pub exposure: Option<f64>,
+    pub adaptive_threshold: Option<f64>,
+    pub min_samples: Option<u64>,
+    pub lookfrom: Option<Vec3>,
+    pub lookat: Option<Vec3>,
+    pub vup: Option<Vec3>,
+    pub vfov: Option<f64>,
+    pub aperture: Option<f64>,
+    pub dist_to_focus: Option<f64>,
+    pub time0: Option<f64>,
+    pub time1: Option<f64>,
+}
+
+impl Overrides {
+    // The camera is rebuilt since it's derived from the (possibly overridden) camera config.
+    // Fails if the environment map can't be loaded
+    pub fn apply(&self, (mut config, world, _): Scene) -> Result<Scene, String> {
+        apply_config(self, &mut config)?;
+        config.check_size()?;
+        let camera = Camera::new(&config.camera_config, config.aspect_ratio);
+
+        Ok((config, world, camera))
+    }
+}
+
+fn apply_config(overrides: &Overrides, config: &mut Config) -> Result<(), String> {
+    if let Some(image_width) = overrides.image_width {
+        config.image_width = image_width;
+        config.image_height = ((image_width as f64) / config.aspect_ratio) as u64;
+    }
+    if let Some(samples_per_pixel) = overrides.samples_per_pixel {
+        config.samples_per_pixel = samples_per_pixel;
+    }
+    if let Some(max_depth) = overrides.max_depth {
+        config.max_depth = max_depth;
+    }
+    if let Some(roulette_depth) = overrides.roulette_depth {
+        config.roulette_depth = roulette_depth;
+    }
+    if let Some(background_color) = overrides.background_color {
+        config.background = Box::new(ConstantBackground::new(background_color));
+    }
+    match overrides.background.as_deref() {
+        None => {}
+        Some("sky") => config.background = Box::new(SkyGradient::default()),
+        Some(file) => {
+            let map = EnvironmentMap::open(file)
+                .map_err(|err| format!("could not load environment map `{}`: {}", file, err))?;
+            config.background = Box::new(map);
+        }
+    }
+    if let Some(tile_size) = overrides.tile_size {
+        config.tile_size = tile_size;
+    }
+    if let Some(seed) = overrides.seed {
+        config.seed = seed;
+    }
+    if let Some(sampler) = overrides.sampler {
+        config.sampler = sampler;
+    }
+    if let Some(filter) = overrides.filter {
+        config.filter = Filter::new(filter);
+    }
+    if let Some(radius) = overrides.filter_radius {
+        config.filter.radius = radius;
+    }
+    if let Some(tone_mapper) = overrides.tone_mapper {
+        config.tone_mapping.operator = tone_mapper;
+    }
+    if let Some(exposure) = overrides.exposure {
+        config.tone_mapping.exposure = exposure;
+    }
+    if let Some(threshold) = overrides.adaptive_threshold {
+        let adaptive = config
+            .adaptive
+            .get_or_insert(AdaptiveSampling::new(threshold));
+        adaptive.threshold = threshold;
+    }
+    // Adaptive sampling can be on from the command line or the scene file
+    if let Some(min_samples) = overrides.min_samples {
+        let adaptive = config.adaptive.as_mut().ok_or_else(|| {
+            String::from(
+                "`--min-samples` needs `--adaptive-threshold` or a scene sampling adaptively",
+            )
+        })?;
+        adaptive.min_samples = min_samples;
+    }
+
+    let camera_config = &mut config.camera_config;
+    if let Some(lookfrom) = overrides.lookfrom {
+        camera_config.lookfrom = lookfrom;
+    }
+    if let Some(lookat) = overrides.lookat {
+        camera_config.lookat = lookat;
+    }
+    if let Some(vup) = overrides.vup {
+        camera_config.vup = vup;
+    }
+    if let Some(vfov) = overrides.vfov {
+        camera_config.vfov = vfov;
+    }
+    if let Some(aperture) = overrides.aperture {
+        camera_config.aperture = aperture;
+    }
+    if let Some(dist_to_focus) = overrides.dist_to_focus {
+        camera_config.dist_to_focus = dist_to_focus;
+    }
+    if let Some(time0) = overrides.time0 {
+        camera_config.time0 = time0;
+    }
+    if let Some(time1) = overrides.time1 {
+        camera_config.time1 = time1;
+    }
+
+    Ok(())
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut scene = None;
+    let mut output = String::from("-");
+    let mut sample_heatmap = None;
+    let mut checkpoint = None;
+    let mut checkpoint_interval = Duration::from_secs(60);
+    let mut pass_samples = None;
+    let mut resume = false;
+    let mut backend = Backend::Rayon;
+    let mut overrides = Overrides::default();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if scene.is_some() {
+                return Err(format!("unexpected argument `{}`", arg));
+            }
+            scene = Some(arg);
+            continue;
+        }
+
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for `{}`", flag))
+        };
+
+        match flag.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-l" | "--list" => return Ok(Command::List),
+            "-o" | "--output" => output = value()?,
+            "--sample-heatmap" => sample_heatmap = Some(value()?),
+            "--checkpoint" => checkpoint = Some(value()?),
+            "--checkpoint-interval" => {
+                checkpoint_interval = Duration::try_from_secs_f64(parse_num(&flag, &value()?)?)
+                    .map_err(|_| format!("`{}` must be 0 or more seconds", flag))?
+            }
+            "--pass-samples" => pass_samples = Some(parse_num(&flag, &value()?)?),
+            "--resume" => resume = true,
+            "--backend" => {
+                backend = match value()?.as_str() {
+                    "rayon" => Backend::Rayon,
+                    "sequential" => Backend::Sequential,
+                    other => return Err(format!("unknown backend `{}`", other)),
+                }
+            }
+            "--image-width" => overrides.image_width = Some(parse_num(&flag, &value()?)?),
+            "--samples-per-pixel" => {
+                overrides.samples_per_pixel = Some(parse_num(&flag, &value()?)?)
+            }
+            "--max-depth" => overrides.max_depth = Some(parse_num(&flag, &value()?)?),
+            "--roulette-depth" => overrides.roulette_depth = Some(parse_num(&flag, &value()?)?),
+            "--background" => overrides.background = Some(value()?),
+            "--background-color" => {
+                overrides.background_color = Some(parse_vec3(&flag, &value()?)?)
+            }
+            "--tile-size" => overrides.tile_size = Some(parse_num(&flag, &value()?)?),
+            "--seed" => overrides.seed = Some(parse_num(&flag, &value()?)?),
+            "--sampler" => overrides.sampler = Some(value()?.parse()?),
+            "--filter" => overrides.filter = Some(value()?.parse()?),
+            "--filter-radius" => overrides.filter_radius = Some(parse_num(&flag, &value()?)?),
+            "--tone-mapper" => overrides.tone_mapper = Some(value()?.parse()?),
+            "--exposure" => overrides.exposure = Some(parse_num(&flag, &value()?)?),
+            "--adaptive-threshold" => {
+                overrides.adaptive_threshold = Some(parse_num(&flag, &value()?)?)
+            }
+            "--min-samples" => overrides.min_samples = Some(parse_num(&flag, &value()?)?),
+            "--lookfrom" => overrides.lookfrom = Some(parse_vec3(&flag, &value()?)?),
+            "--lookat" => overrides.lookat = Some(parse_vec3(&flag, &value()?)?),
+            "--vup" => overrides.vup = Some(parse_vec3(&flag, &value()?)?),
+            "--vfov" => overrides.vfov = Some(parse_num(&flag, &value()?)?),
+            "--aperture" => overrides.aperture = Some(parse_num(&flag, &value()?)?),
+            "--dist-to-focus" => overrides.dist_to_focus = Some(parse_num(&flag, &value()?)?),
+            "--time0" => overrides.time0 = Some(parse_num(&flag, &value()?)?),
+            "--time1" => overrides.time1 = Some(parse_num(&flag, &value()?)?),
+            _ => return Err(format!("unknown option `{}`", flag)),
+        }
+    }
+
+    for path in std::iter::once(&output).chain(&sample_heatmap) {
+        if path != "-" && ImageFormat::from_path(Path::new(path)).is_none() {
+            return Err(format!(
+                "unsupported output format `{}`, expected .png, .jpg, .ppm, .hdr or .exr",
+                path
+            ));
+        }
+    }
+    if sample_heatmap.as_deref() == Some("-") {
+        return Err(String::from("`--sample-heatmap` needs a file"));
+    }
+    if overrides.background.is_some() && overrides.background_color.is_some() {
+        return Err(String::from(
+            "`--background` and `--background-color` can't be used together",
+        ));
+    }
+    if resume && checkpoint.is_none() {
+        return Err(String::from("`--resume` needs `--checkpoint`"));
+    }
+    // Only a scene file can turn on adaptive sampling by itself
+    let scene_file = scene
+        .as_deref()
+        .is_some_and(|scene| scene.ends_with(".toml"));
+    if overrides.min_samples.is_some() && overrides.adaptive_threshold.is_none() && !scene_file {
+        return Err(String::from("`--min-samples` needs `--adaptive-threshold`"));
+    }
+    if pass_samples == Some(0) {
+        return Err(String::from("`--pass-samples` must be greater than 0"));
+    }
+    if overrides.image_width.is_some_and(|width| width < 2) {
+        return Err(String::from("`--image-width` must be at least 2"));
+    }
+    if overrides.samples_per_pixel == Some(0) {
+        return Err(String::from("`--samples-per-pixel` must be greater than 0"));
+    }
+    if overrides.tile_size == Some(0) {
+        return Err(String::from("`--tile-size` must be greater than 0"));
+    }
+    if overrides.filter_radius.is_some_and(|radius| radius <= 0.0) {
+        return Err(String::from("`--filter-radius` must be greater than 0"));
+    }
+    if overrides
+        .adaptive_threshold
+        .is_some_and(|threshold| threshold <= 0.0)
+    {
+        return Err(String::from(
+            "`--adaptive-threshold` must be greater than 0",
+        ));
+    }
+
+    Ok(Command::Render(Box::new(Args {
+        scene: scene.unwrap_or_else(|| String::from("ray_tracing_the_next_week")),
+        output,
+        sample_heatmap,
+        checkpoint,
+        checkpoint_interval,
+        pass_samples,
+        resume,
+        backend,
+        overrides,
+    })))
+}
+
+fn parse_num<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value `{}` for `{}`", value, flag))
+}
+
+fn parse_vec3(flag: &str, value: &str) -> Result<Vec3, String> {
+    let parts = value
+        .split(',')
+        .map(|part| parse_num::<f64>(flag, part))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!(
+            "expected three comma separated numbers for `{}`, got `{}`",
+            flag, value
+        )),
+    }
+}
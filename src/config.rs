@@ -1,4 +1,7 @@
-use crate::{Color, Point3, Vec3};
+use crate::{
+    Background, Color, ConstantBackground, Filter, Point3, SamplerKind, SkyGradient, ToneMapping,
+    Vec3,
+};
 
 pub struct ConfigOptions {
     pub aspect_ratio: f64,
@@ -6,6 +9,7 @@ pub struct ConfigOptions {
     pub samples_per_pixel: u64,
     pub max_depth: u64,
     pub camera_config: CameraConfig,
+    // A constant background, None for the sky gradient
     pub background_color: Option<Color>,
 }
 
@@ -15,8 +19,44 @@ pub struct Config {
     pub image_height: u64,
     pub samples_per_pixel: u64,
     pub max_depth: u64,
+    // Bounces after which Russian roulette may end a path, `max_depth` still caps its length
+    pub roulette_depth: u64,
     pub camera_config: CameraConfig,
-    pub background_color: Color,
+    // What rays leaving the scene see
+    pub background: Box<dyn Background>,
+    // Width and height in pixels of the square tiles rendered in parallel
+    pub tile_size: u64,
+    // Renders with the same seed are identical
+    pub seed: u64,
+    // Where the pixel, lens, time, light and material samples come from
+    pub sampler: SamplerKind,
+    // How samples are weighted into the pixels around them
+    pub filter: Filter,
+    // Exposure and tone curve applied when writing 8 bit images
+    pub tone_mapping: ToneMapping,
+    // None takes `samples_per_pixel` samples in every pixel
+    pub adaptive: Option<AdaptiveSampling>,
+    // Fingerprint of what the scene was built from, like the bytes of its scene file, so a
+    // checkpoint of another scene isn't resumed
+    pub source_hash: u64,
+}
+
+// Stops sampling a pixel once the standard error of its mean luminance is below `threshold`
+// times the mean. `samples_per_pixel` stays the upper bound
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct AdaptiveSampling {
+    pub threshold: f64,
+    // Taken before the error is trusted, so pixels that missed a small light keep going
+    pub min_samples: u64,
+}
+
+impl AdaptiveSampling {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            min_samples: 16,
+        }
+    }
 }
 
 impl Config {
@@ -27,12 +67,33 @@ impl Config {
             image_height: ((options.image_width as f64) / options.aspect_ratio) as u64,
             samples_per_pixel: options.samples_per_pixel,
             max_depth: options.max_depth,
+            roulette_depth: 3,
             camera_config: options.camera_config,
-            background_color: options
-                .background_color
-                .unwrap_or(Color::new(0.70, 0.80, 1.00)),
+            background: match options.background_color {
+                Some(color) => Box::new(ConstantBackground::new(color)),
+                None => Box::new(SkyGradient::default()),
+            },
+            tile_size: 32,
+            seed: 0,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            tone_mapping: ToneMapping::default(),
+            adaptive: None,
+            source_hash: 0,
         }
     }
+
+    // Pixels are spread over [0, 1] by dividing by the size less one, so each side needs two
+    pub fn check_size(&self) -> Result<(), String> {
+        if self.image_width < 2 || self.image_height < 2 {
+            return Err(format!(
+                "an image of {}x{} pixels at an aspect ratio of {} is too small, both sides need at least 2 pixels",
+                self.image_width, self.image_height, self.aspect_ratio
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 pub struct CameraConfigOptions {
@@ -1,6 +1,6 @@
-use std::{f64::INFINITY, sync::Arc};
+use std::sync::Arc;
 
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 use crate::{Color, HitRecord, Hitable, Isotropic, SolidColor, Texture, Vec3};
 
@@ -34,9 +34,17 @@ impl<T: Texture + 'static> Hitable for ConstantMedium<T> {
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<crate::AABB> {
         self.boundary.bounding_box(time0, time1)
     }
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<crate::HitRecord> {
-        let mut rec1 = self.boundary.hit(r, -INFINITY, INFINITY)?;
-        let mut rec2 = self.boundary.hit(r, rec1.t + 0.001, INFINITY)?;
+    fn hit(
+        &self,
+        r: &crate::Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<crate::HitRecord> {
+        let mut rec1 = self
+            .boundary
+            .hit(r, f64::NEG_INFINITY, f64::INFINITY, rng)?;
+        let mut rec2 = self.boundary.hit(r, rec1.t + 0.001, f64::INFINITY, rng)?;
 
         if rec1.t < t_min {
             rec1.t = t_min;
@@ -56,7 +64,6 @@ impl<T: Texture + 'static> Hitable for ConstantMedium<T> {
 
         let ray_length = r.direction().length();
         let distance_inside_boundary = (rec2.t - rec1.t) * ray_length;
-        let mut rng = rand::thread_rng();
         let hit_distance = self.neg_inv_density * rng.gen::<f64>().log10();
 
         if hit_distance > distance_inside_boundary {
@@ -78,6 +85,8 @@ impl<T: Texture + 'static> Hitable for ConstantMedium<T> {
             mat,
             u: 0.0,
             v: 0.0,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         };
 
         Some(rec)
@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+use rand::RngCore;
+
 use crate::{world::HittableList, Hitable, Material, Plane, Point3, AABB};
 
 pub struct Cube {
@@ -11,63 +13,62 @@ pub struct Cube {
 
 impl Cube {
     pub fn new(p0: Point3, p1: Point3, mat: Arc<dyn Material>) -> Self {
-        let mut sides: HittableList = Vec::new();
-
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::XY,
-            mat.clone(),
-            p0.x(),
-            p1.x(),
-            p0.y(),
-            p1.y(),
-            p1.z(),
-        )));
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::XY,
-            mat.clone(),
-            p0.x(),
-            p1.x(),
-            p0.y(),
-            p1.y(),
-            p0.z(),
-        )));
-
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::ZX,
-            mat.clone(),
-            p0.z(),
-            p1.z(),
-            p0.x(),
-            p1.x(),
-            p1.y(),
-        )));
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::ZX,
-            mat.clone(),
-            p0.z(),
-            p1.z(),
-            p0.x(),
-            p1.x(),
-            p0.y(),
-        )));
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::YZ,
-            mat.clone(),
-            p0.y(),
-            p1.y(),
-            p0.z(),
-            p1.z(),
-            p1.x(),
-        )));
-        sides.push(Box::new(Plane::new(
-            crate::PlaneOrientation::YZ,
-            mat.clone(),
-            p0.y(),
-            p1.y(),
-            p0.z(),
-            p1.z(),
-            p0.x(),
-        )));
+        let sides: HittableList = vec![
+            Box::new(Plane::new(
+                crate::PlaneOrientation::XY,
+                mat.clone(),
+                p0.x(),
+                p1.x(),
+                p0.y(),
+                p1.y(),
+                p1.z(),
+            )),
+            Box::new(Plane::new(
+                crate::PlaneOrientation::XY,
+                mat.clone(),
+                p0.x(),
+                p1.x(),
+                p0.y(),
+                p1.y(),
+                p0.z(),
+            )),
+            Box::new(Plane::new(
+                crate::PlaneOrientation::ZX,
+                mat.clone(),
+                p0.z(),
+                p1.z(),
+                p0.x(),
+                p1.x(),
+                p1.y(),
+            )),
+            Box::new(Plane::new(
+                crate::PlaneOrientation::ZX,
+                mat.clone(),
+                p0.z(),
+                p1.z(),
+                p0.x(),
+                p1.x(),
+                p0.y(),
+            )),
+            Box::new(Plane::new(
+                crate::PlaneOrientation::YZ,
+                mat.clone(),
+                p0.y(),
+                p1.y(),
+                p0.z(),
+                p1.z(),
+                p1.x(),
+            )),
+            Box::new(Plane::new(
+                crate::PlaneOrientation::YZ,
+                mat.clone(),
+                p0.y(),
+                p1.y(),
+                p0.z(),
+                p1.z(),
+                p0.x(),
+            )),
+        ];
 
         Self {
             min: p0,
@@ -82,7 +83,13 @@ impl Hitable for Cube {
         let output_box = AABB::new(self.min, self.max);
         Some(output_box)
     }
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<crate::HitRecord> {
-        self.sides.hit(r, t_min, t_max)
+    fn hit(
+        &self,
+        r: &crate::Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<crate::HitRecord> {
+        self.sides.hit(r, t_min, t_max, rng)
     }
 }
@@ -0,0 +1,427 @@
+use std::f64::consts::PI;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use crate::tonemap::srgb_decode;
+use crate::{Color, Framebuffer, Tile};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum FilterKind {
+    // Plain average of the samples inside the pixel
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    // Windowed sinc with 3 lobes
+    Lanczos,
+}
+
+impl FilterKind {
+    // Reach in pixels the filter is designed for
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+impl FromStr for FilterKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "box" => Ok(FilterKind::Box),
+            "tent" => Ok(FilterKind::Tent),
+            "gaussian" => Ok(FilterKind::Gaussian),
+            "mitchell" => Ok(FilterKind::Mitchell),
+            "lanczos" => Ok(FilterKind::Lanczos),
+            other => Err(format!(
+                "unknown filter `{}`, expected box, tent, gaussian, mitchell or lanczos",
+                other
+            )),
+        }
+    }
+}
+
+// Reconstruction filter, weights a sample by its offset from the pixel center. Separable, so
+// the weight is the product of the 1D filter along x and y
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // Samples further than this from the pixel center along x or y get no weight
+    pub radius: f64,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            radius: kind.default_radius(),
+        }
+    }
+    pub fn with_radius(kind: FilterKind, radius: f64) -> Self {
+        Self { kind, radius }
+    }
+
+    pub fn eval(&self, dx: f64, dy: f64) -> f64 {
+        self.eval_1d(dx) * self.eval_1d(dy)
+    }
+    fn eval_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        match self.kind {
+            // Half open, so a sample on the border between two pixels only counts for one
+            FilterKind::Box => {
+                if (-r..r).contains(&x) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            FilterKind::Tent => (r - x.abs()).max(0.0),
+            // Shifted down to reach 0 at the radius
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let gaussian = |x: f64| (-x * x / (2.0 * sigma * sigma)).exp();
+                (gaussian(x) - gaussian(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell(2.0 * x / r),
+            FilterKind::Lanczos => {
+                const LOBES: f64 = 3.0;
+                if x.abs() >= r {
+                    return 0.0;
+                }
+                let x = x * LOBES / r;
+                sinc(x) * sinc(x / LOBES)
+            }
+        }
+    }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(FilterKind::default())
+    }
+}
+
+// Cubic on [-2, 2]
+fn mitchell(x: f64) -> f64 {
+    const B: f64 = 1.0 / 3.0;
+    const C: f64 = 1.0 / 3.0;
+    let x = x.abs();
+    let value = if x < 1.0 {
+        (12.0 - 9.0 * B - 6.0 * C) * x.powi(3)
+            + (-18.0 + 12.0 * B + 6.0 * C) * x.powi(2)
+            + (6.0 - 2.0 * B)
+    } else if x < 2.0 {
+        (-B - 6.0 * C) * x.powi(3)
+            + (6.0 * B + 30.0 * C) * x.powi(2)
+            + (-12.0 * B - 48.0 * C) * x
+            + (8.0 * B + 24.0 * C)
+    } else {
+        0.0
+    };
+
+    value / 6.0
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+// Filter weighted sums of the samples around a rectangle of pixels. Pixel (x, y) covers
+// [x, x + 1) x [y, y + 1) with y counted from the top of the image
+pub struct Film {
+    filter: Filter,
+    x0: u64,
+    y0: u64,
+    width: u64,
+    height: u64,
+    sums: Vec<Color>,
+    weights: Vec<f64>,
+    // Samples taken for each pixel, unlike the weights not spread by the filter
+    samples: Vec<u64>,
+    // Sum of the luminance of those samples and of its square, for their variance
+    luminance: Vec<[f64; 2]>,
+}
+
+impl Film {
+    pub fn new(width: u64, height: u64, filter: Filter) -> Self {
+        Self::with_bounds(filter, 0, 0, width, height)
+    }
+    fn with_bounds(filter: Filter, x0: u64, y0: u64, width: u64, height: u64) -> Self {
+        Self {
+            filter,
+            x0,
+            y0,
+            width,
+            height,
+            sums: vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize],
+            weights: vec![0.0; (width * height) as usize],
+            samples: vec![0; (width * height) as usize],
+            luminance: vec![[0.0; 2]; (width * height) as usize],
+        }
+    }
+    // Film for the samples of `tile`, grown by the reach of the filter so every pixel they
+    // touch is inside. Merged back with `merge_tile`
+    pub fn tile(&self, tile: Tile) -> Film {
+        let reach = (self.filter.radius + 0.5).ceil() as u64;
+        let x0 = tile.x0.saturating_sub(reach);
+        let y0 = tile.y0.saturating_sub(reach);
+        let x1 = (tile.x1 + reach).min(self.width);
+        let y1 = (tile.y1 + reach).min(self.height);
+
+        Self::with_bounds(self.filter, x0, y0, x1 - x0, y1 - y0)
+    }
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+    fn index(&self, x: u64, y: u64) -> usize {
+        ((y - self.y0) * self.width + (x - self.x0)) as usize
+    }
+
+    // Adds `color` seen at image position (x, y) to every pixel whose filter reaches it
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color) {
+        let r = self.filter.radius;
+        let min_x = ((x - 0.5 - r).ceil() as i64).max(self.x0 as i64);
+        let max_x = ((x - 0.5 + r).floor() as i64).min((self.x0 + self.width) as i64 - 1);
+        let min_y = ((y - 0.5 - r).ceil() as i64).max(self.y0 as i64);
+        let max_y = ((y - 0.5 + r).floor() as i64).min((self.y0 + self.height) as i64 - 1);
+
+        for py in min_y..=max_y {
+            for px in min_x..=max_x {
+                let weight = self
+                    .filter
+                    .eval(x - (px as f64 + 0.5), y - (py as f64 + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+                let index = self.index(px as u64, py as u64);
+                self.sums[index] += weight * color;
+                self.weights[index] += weight;
+            }
+        }
+    }
+
+    // Counts a sample taken for pixel (x, y), the color goes through `add_sample`
+    pub fn count_sample(&mut self, x: u64, y: u64, luminance: f64) {
+        let index = self.index(x, y);
+        self.samples[index] += 1;
+        self.luminance[index][0] += luminance;
+        self.luminance[index][1] += luminance * luminance;
+    }
+    pub fn samples(&self, x: u64, y: u64) -> u64 {
+        self.samples[self.index(x, y)]
+    }
+    // Number of samples of pixel (x, y) with the sum of their luminance and its square
+    pub fn luminance_sums(&self, x: u64, y: u64) -> (u64, f64, f64) {
+        let index = self.index(x, y);
+        let [sum, sum_squared] = self.luminance[index];
+        (self.samples[index], sum, sum_squared)
+    }
+
+    pub fn merge_tile(&mut self, tile: &Film) {
+        for y in 0..tile.height {
+            for x in 0..tile.width {
+                let from = (y * tile.width + x) as usize;
+                let to = self.index(tile.x0 + x, tile.y0 + y);
+                self.sums[to] += tile.sums[from];
+                self.weights[to] += tile.weights[from];
+                self.samples[to] += tile.samples[from];
+                self.luminance[to][0] += tile.luminance[from][0];
+                self.luminance[to][1] += tile.luminance[from][1];
+            }
+        }
+    }
+
+    // Weighted average per pixel. Negative lobes can push dark pixels below 0, those are
+    // clamped to black
+    pub fn to_framebuffer(&self) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = (y * self.width + x) as usize;
+                let weight = self.weights[index];
+                let color = if weight > 0.0 {
+                    self.sums[index] / weight
+                } else {
+                    Color::new(0.0, 0.0, 0.0)
+                };
+                framebuffer.set(
+                    x,
+                    y,
+                    Color::new(color.x().max(0.0), color.y().max(0.0), color.z().max(0.0)),
+                );
+            }
+        }
+
+        framebuffer
+    }
+
+    // Raw sums as little endian numbers, the size first. Only for a whole image, the filter
+    // isn't stored
+    pub(crate) fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&self.width.to_le_bytes())?;
+        out.write_all(&self.height.to_le_bytes())?;
+        for index in 0..self.sums.len() {
+            let sum = self.sums[index];
+            let [luminance, luminance_squared] = self.luminance[index];
+            for value in [
+                sum.x(),
+                sum.y(),
+                sum.z(),
+                self.weights[index],
+                luminance,
+                luminance_squared,
+            ] {
+                out.write_all(&value.to_le_bytes())?;
+            }
+            out.write_all(&self.samples[index].to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+    // Fails unless the stored image is `width` x `height`
+    pub(crate) fn read_from(
+        input: &mut impl Read,
+        width: u64,
+        height: u64,
+        filter: Filter,
+    ) -> io::Result<Film> {
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            input.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        let size = (read_u64()?, read_u64()?);
+        if size != (width, height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the image is {}x{}, expected {}x{}",
+                    size.0, size.1, width, height
+                ),
+            ));
+        }
+        let mut film = Film::new(width, height, filter);
+        for index in 0..film.sums.len() {
+            let mut values = [0.0; 6];
+            for value in &mut values {
+                *value = f64::from_bits(read_u64()?);
+            }
+            let [r, g, b, weight, luminance, luminance_squared] = values;
+            film.sums[index] = Color::new(r, g, b);
+            film.weights[index] = weight;
+            film.luminance[index] = [luminance, luminance_squared];
+            film.samples[index] = read_u64()?;
+        }
+
+        Ok(film)
+    }
+
+    // Samples per pixel relative to the busiest pixel, from dark purple (none) to yellow
+    pub fn sample_heatmap(&self) -> Framebuffer {
+        let max = self.samples.iter().copied().max().unwrap_or(0).max(1);
+        let mut framebuffer = Framebuffer::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let count = self.samples[(y * self.width + x) as usize];
+                framebuffer.set(x, y, heatmap_color(count as f64 / max as f64));
+            }
+        }
+
+        framebuffer
+    }
+}
+
+// Piecewise linear approximation of viridis, `t` in [0, 1]
+fn heatmap_color(t: f64) -> Color {
+    const STOPS: [[f64; 3]; 5] = [
+        [0.267, 0.005, 0.329],
+        [0.231, 0.322, 0.545],
+        [0.129, 0.569, 0.549],
+        [0.369, 0.788, 0.384],
+        [0.993, 0.906, 0.144],
+    ];
+    let t = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let i = (t as usize).min(STOPS.len() - 2);
+    let f = t - i as f64;
+    let channel = |c: usize| srgb_decode((1.0 - f) * STOPS[i][c] + f * STOPS[i + 1][c]);
+
+    Color::new(channel(0), channel(1), channel(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    // Four by four samples in each pixel of a `width` x `height` film, colored by `color`
+    fn fill(film: &mut Film, color: impl Fn(f64, f64) -> Color) {
+        for y in 0..film.height * 4 {
+            for x in 0..film.width * 4 {
+                let (x, y) = ((x as f64 + 0.5) / 4.0, (y as f64 + 0.5) / 4.0);
+                film.add_sample(x, y, color(x, y));
+                film.count_sample(x as u64, y as u64, color(x, y).y());
+            }
+        }
+    }
+
+    #[test]
+    fn filters_keep_a_flat_image_flat() {
+        let color = Color::new(0.25, 0.5, 2.0);
+        for kind in KINDS {
+            let mut film = Film::new(8, 6, Filter::new(kind));
+            fill(&mut film, |_, _| color);
+            let framebuffer = film.to_framebuffer();
+            for &pixel in framebuffer.pixels() {
+                let error = (pixel - color).length();
+                assert!(error < 1.0e-9, "{:?} is off by {}", kind, error);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let filter = Filter::new(FilterKind::Mitchell);
+        let mut film = Film::new(5, 3, filter);
+        fill(&mut film, |x, y| Color::new(x, y, x * y));
+
+        let mut bytes = Vec::new();
+        film.write_to(&mut bytes).unwrap();
+        let read = Film::read_from(&mut bytes.as_slice(), 5, 3, filter).unwrap();
+        assert_eq!(read.weights, film.weights);
+        assert_eq!(read.samples, film.samples);
+        assert_eq!(read.luminance, film.luminance);
+        for (a, b) in read.sums.iter().zip(&film.sums) {
+            assert_eq!([a.x(), a.y(), a.z()], [b.x(), b.y(), b.z()]);
+        }
+
+        let wrong_size = Film::read_from(&mut bytes.as_slice(), 3, 5, filter);
+        assert_eq!(
+            wrong_size.err().map(|err| err.kind()),
+            Some(io::ErrorKind::InvalidData)
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use crate::{aabb, material::Material, Point3, Ray, Vec3, AABB};
+use rand::RngCore;
+
+use crate::{aabb, material::Material, Point3, Ray, Sampler, Vec3, AABB};
 
 pub struct HitRecord {
     pub p: Point3,
@@ -10,10 +12,14 @@ pub struct HitRecord {
     pub front_face: bool,
     pub u: f64,
     pub v: f64,
+    // Derivatives of `p` along `u` and `v`, neither normalized nor perpendicular to `normal`.
+    // Zero where the surface has no texture coordinates to follow
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
 }
 
 impl HitRecord {
-    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) -> () {
+    pub fn set_face_normal(&mut self, r: &Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face {
             outward_normal
@@ -24,8 +30,24 @@ impl HitRecord {
 }
 
 pub trait Hitable: Send + Sync {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord>;
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB>;
+    // Whether the renderer should sample this object directly as a light
+    fn is_light(&self) -> bool {
+        false
+    }
+    // Whether `pdf_value` and `random` can sample the object, whatever its material
+    fn can_sample(&self) -> bool {
+        false
+    }
+    // Solid angle density of `random` picking `direction` from `origin`
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+    // Direction from `origin` towards a random point on the object
+    fn random(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub struct Translate {
@@ -47,16 +69,28 @@ impl Hitable for Translate {
             bbox.max() + self.offest,
         ))
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let moved_r = Ray::new(r.origin() - self.offest, r.direction(), r.time());
 
-        let mut rec = self.obj.hit(&moved_r, t_min, t_max)?;
+        let mut rec = self.obj.hit(&moved_r, t_min, t_max, rng)?;
 
+        // The normal and `front_face` of the inner hit hold as they are
         rec.p += self.offest;
-        rec.set_face_normal(&moved_r, rec.normal);
 
         Some(rec)
     }
+    fn is_light(&self) -> bool {
+        self.obj.is_light()
+    }
+    fn can_sample(&self) -> bool {
+        self.obj.can_sample()
+    }
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.obj.pdf_value(origin - self.offest, direction)
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.obj.random(origin - self.offest, sampler)
+    }
 }
 
 pub struct RotateY {
@@ -70,6 +104,21 @@ impl RotateY {
     pub fn bbox(&self) -> Option<AABB> {
         self.bbox
     }
+    // Into the space of the rotated object
+    fn rotate_into(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+    fn rotate_back(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
     pub fn new(obj: Box<dyn Hitable>, angle: f64) -> Self {
         let radians = angle.to_radians();
         let sin_theta = radians.sin();
@@ -124,7 +173,7 @@ impl Hitable for RotateY {
     fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
         self.bbox()
     }
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let mut origin = r.origin();
         let mut direction = r.direction();
 
@@ -136,7 +185,7 @@ impl Hitable for RotateY {
 
         let rotated = Ray::new(origin, direction, r.time());
 
-        let mut rec = self.obj.hit(&rotated, t_min, t_max)?;
+        let mut rec = self.obj.hit(&rotated, t_min, t_max, rng)?;
 
         let mut p = rec.p;
         let mut normal = rec.normal;
@@ -147,9 +196,25 @@ impl Hitable for RotateY {
         normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
         normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
 
+        // The normal is already flipped against the ray, rotating it keeps `front_face`
         rec.p = p;
-        rec.set_face_normal(&rotated, normal);
+        rec.normal = normal;
+        rec.tangent = self.rotate_back(rec.tangent);
+        rec.bitangent = self.rotate_back(rec.bitangent);
 
         Some(rec)
     }
+    fn is_light(&self) -> bool {
+        self.obj.is_light()
+    }
+    fn can_sample(&self) -> bool {
+        self.obj.can_sample()
+    }
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.obj
+            .pdf_value(self.rotate_into(origin), self.rotate_into(direction))
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.rotate_back(self.obj.random(self.rotate_into(origin), sampler))
+    }
 }
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use rand::RngCore;
+
+use crate::transform::Affine;
+use crate::{HitRecord, Hitable, Material, Matrix4, Point3, Ray, Sampler, Vec3, AABB};
+
+// A placement of geometry shared between many instances, like a mesh whose BVH is built once.
+// Each instance only stores its matrix and optionally a material used instead of the
+// geometry's own
+pub struct Instance {
+    geometry: Arc<dyn Hitable>,
+    affine: Affine,
+    material: Option<Arc<dyn Material>>,
+}
+
+impl Instance {
+    pub fn new(geometry: Arc<dyn Hitable>, matrix: Matrix4) -> Self {
+        Self {
+            geometry,
+            affine: Affine::new(matrix),
+            material: None,
+        }
+    }
+    pub fn with_material(
+        geometry: Arc<dyn Hitable>,
+        matrix: Matrix4,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            geometry,
+            affine: Affine::new(matrix),
+            material: Some(material),
+        }
+    }
+    pub fn geometry(&self) -> &Arc<dyn Hitable> {
+        &self.geometry
+    }
+    pub fn matrix(&self) -> Matrix4 {
+        self.affine.matrix()
+    }
+}
+
+impl Hitable for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        let mut rec = self
+            .affine
+            .hit(self.geometry.as_ref(), r, t_min, t_max, rng)?;
+        if let Some(material) = &self.material {
+            rec.mat = material.clone();
+        }
+
+        Some(rec)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.affine
+            .bounding_box(self.geometry.as_ref(), time0, time1)
+    }
+    // An emissive override only makes a light of geometry that can be sampled, meshes and
+    // boxes are left to material sampling
+    fn is_light(&self) -> bool {
+        match &self.material {
+            Some(material) => {
+                material.is_emissive() && self.affine.can_sample(self.geometry.as_ref())
+            }
+            None => self.affine.is_light(self.geometry.as_ref()),
+        }
+    }
+    fn can_sample(&self) -> bool {
+        self.affine.can_sample(self.geometry.as_ref())
+    }
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.affine
+            .pdf_value(self.geometry.as_ref(), origin, direction)
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.affine.random(self.geometry.as_ref(), origin, sampler)
+    }
+}
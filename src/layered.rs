@@ -0,0 +1,235 @@
+use std::sync::Arc;
+
+use crate::microfacet::{fresnel_dielectric, Ggx};
+use crate::{
+    Color, HitRecord, Lobe, Material, Onb, Point3, Ray, Sampler, ScatterRecord, SolidColor,
+    Texture, Vec3,
+};
+
+// Two materials blended on one surface, `weight` is the fraction of `second` read from the mean
+// of the channels of a texture. One of them is picked at random to scatter, and the densities
+// of both are summed so light sampling is weighted against the whole mix
+pub struct MixMaterial<T: Texture> {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    weight: T,
+}
+
+impl<T: Texture> MixMaterial<T> {
+    pub fn new(first: Arc<dyn Material>, second: Arc<dyn Material>, weight: T) -> Self {
+        Self {
+            first,
+            second,
+            weight,
+        }
+    }
+
+    fn weight(&self, u: f64, v: f64, point: Point3) -> f64 {
+        let weight = self.weight.value(u, v, point);
+        ((weight.x() + weight.y() + weight.z()) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+impl MixMaterial<SolidColor> {
+    pub fn constant(first: Arc<dyn Material>, second: Arc<dyn Material>, weight: f64) -> Self {
+        Self::new(first, second, SolidColor::from_rbg(weight, weight, weight))
+    }
+}
+
+impl<T: Texture> Material for MixMaterial<T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let material = if sampler.next_1d() < self.weight(rec.u, rec.v, rec.p) {
+            &self.second
+        } else {
+            &self.first
+        };
+
+        // A specular lobe keeps its attenuation, its weight in the mix cancels with the chance of
+        // picking it
+        let mut srec = material.scatter(r_in, rec, sampler)?;
+        if let Lobe::Diffuse { .. } = srec.lobe {
+            let wi = srec.ray.direction().normalized();
+            let wo = (-1.0) * r_in.direction().normalized();
+            let pdf = self.pdf(rec, wi, wo);
+            if pdf <= 0.0 {
+                return None;
+            }
+            srec.attenuation = self.eval(rec, wi, wo) / pdf;
+            srec.lobe = Lobe::Diffuse { pdf };
+        }
+
+        Some(srec)
+    }
+    fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
+        let weight = self.weight(u, v, point);
+        (1.0 - weight) * self.first.emitted(u, v, point) + weight * self.second.emitted(u, v, point)
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+        let weight = self.weight(rec.u, rec.v, rec.p);
+        (1.0 - weight) * self.first.eval(rec, wi, wo) + weight * self.second.eval(rec, wi, wo)
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+        let weight = self.weight(rec.u, rec.v, rec.p);
+        (1.0 - weight) * self.first.pdf(rec, wi, wo) + weight * self.second.pdf(rec, wi, wo)
+    }
+    fn is_emissive(&self) -> bool {
+        self.first.is_emissive() || self.second.is_emissive()
+    }
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        let weight = self.weight(rec.u, rec.v, rec.p);
+        (1.0 - weight) * self.first.transmittance(rec, distance)
+            + weight * self.second.transmittance(rec, distance)
+    }
+}
+
+// A clear dielectric coat like varnish or lacquer over any `base` material, on both sides of the
+// surface. The coat reflects its Fresnel share with GGX of the textured roughness, and the base
+// only gets the light that goes through the coat on the way in and on the way out, tinted by the
+// coat and more so at grazing angles. The base sees the directions unrefracted
+pub struct Coated<T: Texture> {
+    base: Arc<dyn Material>,
+    ir: f64,
+    roughness: T,
+    // Left of the light going straight through the coat once
+    tint: Color,
+}
+
+impl<T: Texture> Coated<T> {
+    pub fn new(base: Arc<dyn Material>, index_of_refraction: f64, roughness: T) -> Self {
+        Self {
+            base,
+            ir: index_of_refraction,
+            roughness,
+            tint: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> Ggx {
+        let roughness = self.roughness.value(rec.u, rec.v, rec.p);
+        Ggx::from_roughness((roughness.x() + roughness.y() + roughness.z()) / 3.0)
+    }
+    // Light crossing the coat at `cos_theta` to the normal, less what it reflects and absorbs
+    fn transmitted(&self, cos_theta: f64) -> Color {
+        let cos_i = cos_theta.abs().min(1.0);
+        let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (self.ir * self.ir)).sqrt();
+        let channel = |tint: f64| tint.clamp(0.0, 1.0).powf(1.0 / cos_t);
+        let transmitted = 1.0 - fresnel_dielectric(cos_i, self.ir);
+
+        transmitted
+            * Color::new(
+                channel(self.tint.x()),
+                channel(self.tint.y()),
+                channel(self.tint.z()),
+            )
+    }
+    // Reflection off the rough coat alone and its density, none for a smooth coat
+    fn coat(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> (f64, f64) {
+        let ggx = self.distribution(rec);
+        let onb = Onb::from_w(rec.normal);
+        let (wi, wo) = (onb.to_local(wi), onb.to_local(wo));
+        if ggx.is_specular() || wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let h = (wi + wo).normalized();
+        let fresnel = fresnel_dielectric(wo.dot(h), self.ir);
+        let eval = ggx.d(h) * ggx.g2(wo, wi) * fresnel / (4.0 * wo.z());
+        let pdf = ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h));
+
+        (eval, pdf)
+    }
+    // Chance of sampling the coat rather than the base
+    fn coat_probability(&self, rec: &HitRecord, wo: Vec3) -> f64 {
+        fresnel_dielectric(rec.normal.dot(wo), self.ir)
+    }
+}
+
+impl<T: Texture> Material for Coated<T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let wo = (-1.0) * r_in.direction().normalized();
+        let p_coat = self.coat_probability(rec, wo);
+
+        if sampler.next_1d() < p_coat {
+            let ggx = self.distribution(rec);
+            if ggx.is_specular() {
+                // The Fresnel reflectance cancels with the chance of picking the coat
+                return Some(ScatterRecord {
+                    ray: Ray::new(rec.p, ((-1.0) * wo).reflect(rec.normal), r_in.time()),
+                    attenuation: Color::new(1.0, 1.0, 1.0),
+                    lobe: Lobe::Specular,
+                });
+            }
+            let onb = Onb::from_w(rec.normal);
+            let local_wo = onb.to_local(wo);
+            let h = ggx.sample_visible_normal(local_wo, sampler.next_2d());
+            let wi = ((-1.0) * local_wo).reflect(h);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let wi = onb.local(wi).normalized();
+            let pdf = self.pdf(rec, wi, wo);
+            if pdf <= 0.0 {
+                return None;
+            }
+            return Some(ScatterRecord {
+                ray: Ray::new(rec.p, wi, r_in.time()),
+                attenuation: self.eval(rec, wi, wo) / pdf,
+                lobe: Lobe::Diffuse { pdf },
+            });
+        }
+
+        let mut srec = self.base.scatter(r_in, rec, sampler)?;
+        let wi = srec.ray.direction().normalized();
+        match srec.lobe {
+            Lobe::Specular => {
+                let through =
+                    self.transmitted(rec.normal.dot(wo)) * self.transmitted(rec.normal.dot(wi));
+                srec.attenuation = srec.attenuation * through / (1.0 - p_coat);
+            }
+            Lobe::Diffuse { .. } => {
+                let pdf = self.pdf(rec, wi, wo);
+                if pdf <= 0.0 {
+                    return None;
+                }
+                srec.attenuation = self.eval(rec, wi, wo) / pdf;
+                srec.lobe = Lobe::Diffuse { pdf };
+            }
+        }
+
+        Some(srec)
+    }
+    fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
+        self.base.emitted(u, v, point)
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+        let (coat, _) = self.coat(rec, wi, wo);
+        let through = self.transmitted(rec.normal.dot(wo)) * self.transmitted(rec.normal.dot(wi));
+
+        Color::new(coat, coat, coat) + through * self.base.eval(rec, wi, wo)
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+        let p_coat = self.coat_probability(rec, wo);
+        let (_, coat_pdf) = self.coat(rec, wi, wo);
+
+        p_coat * coat_pdf + (1.0 - p_coat) * self.base.pdf(rec, wi, wo)
+    }
+    fn is_emissive(&self) -> bool {
+        self.base.is_emissive()
+    }
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        self.base.transmittance(rec, distance)
+    }
+}
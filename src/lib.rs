@@ -1,31 +1,72 @@
 mod aabb;
+mod background;
 mod bvh;
 mod camera;
+mod checkpoint;
 mod config;
 mod constant_medium;
 mod cube;
+mod film;
 mod hit;
+mod instance;
+mod layered;
 mod material;
+mod microfacet;
+mod normal_map;
+mod obj;
+mod onb;
+mod output;
+mod pcg;
 mod perlin;
+mod principled;
+mod progress;
 mod ray;
 mod rect;
+mod renderer;
+mod sampler;
+mod scene_file;
 mod scenes;
 mod sphere;
 mod texture;
+mod tonemap;
+mod transform;
+mod triangle;
 mod vec3;
 mod world;
 pub use aabb::AABB;
+pub use background::{Background, ConstantBackground, EnvironmentMap, SkyGradient};
+pub use bvh::{BVHOptions, BVHStats, BVH};
 pub use camera::Camera;
+pub use checkpoint::Checkpoint;
 pub use config::*;
 pub use constant_medium::*;
 pub use cube::*;
+pub use film::{Film, Filter, FilterKind};
 pub use hit::*;
+pub use instance::Instance;
+pub use layered::{Coated, MixMaterial};
 pub use material::*;
+pub use normal_map::{BumpMap, NormalMap};
+pub use obj::{load_obj, ObjError};
+pub use onb::Onb;
+pub use output::*;
+pub use pcg::Pcg32;
 pub use perlin::*;
+pub use principled::Principled;
+pub use progress::*;
 pub use ray::Ray;
 pub use rect::*;
+pub use renderer::{Backend, Renderer, Tile};
+pub use sampler::{
+    BlueNoiseSampler, HaltonSampler, RandomSampler, Sampler, SamplerKind, SobolSampler,
+    StratifiedSampler,
+};
+pub use scene_file::{load_scene_file, parse_scene, SceneFileError};
 pub use scenes::*;
 pub use sphere::*;
 pub use texture::*;
+pub use tonemap::{ToneMapper, ToneMapping};
+pub use transform::{Matrix4, Transform};
+pub use triangle::*;
 pub use vec3::{Color, Point3, Vec3};
 pub use world::{HittableList, World};
@@ -1,12 +1,54 @@
-use rand::Rng;
+use std::f64::consts::PI;
 
-use crate::{Color, HitRecord, Point3, Ray, SolidColor, Texture, Vec3};
+use crate::microfacet::{fresnel_conductor, fresnel_dielectric, Ggx};
+use crate::{Color, HitRecord, Onb, Point3, Ray, Sampler, SolidColor, Texture, Vec3};
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Lobe {
+    // Delta distribution like a mirror or glass, can't be evaluated for other directions so
+    // light sampling skips it
+    Specular,
+    // `ray` was sampled with density `pdf`, `eval` and `pdf` work for any direction
+    Diffuse { pdf: f64 },
+}
+
+pub struct ScatterRecord {
+    pub ray: Ray,
+    // `eval / pdf` for the sampled direction of a diffuse lobe, the reflectance of a specular one
+    pub attenuation: Color,
+    pub lobe: Lobe,
+}
+
+// Directions are unit vectors pointing away from the surface, `wo` towards the viewer and
+// `wi` towards where the light comes from
 pub trait Material: Send + Sync {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
     fn emitted(&self, _u: f64, _v: f64, _point: Point3) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
+    // BSDF times the cosine at `wi`, so the light scattered towards `wo` is `eval` times the
+    // light arriving from `wi`. Black for specular lobes
+    fn eval(&self, _rec: &HitRecord, _wi: Vec3, _wo: Vec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+    // Density with which `scatter` picks `wi` given `wo`, 0 for specular lobes
+    fn pdf(&self, _rec: &HitRecord, _wi: Vec3, _wo: Vec3) -> f64 {
+        0.0
+    }
+    // Objects with an emissive material are sampled directly by the renderer
+    fn is_emissive(&self) -> bool {
+        false
+    }
+    // Fraction of the light left after travelling `distance` to `rec`, absorbed inside the
+    // object when `rec` is on the inside of its surface
+    fn transmittance(&self, _rec: &HitRecord, _distance: f64) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
 pub struct Lambertian<T: Texture> {
     albedo: T,
@@ -18,16 +60,32 @@ impl<T: Texture> Lambertian<T> {
     }
 }
 
+// Cosine weighted sampling, so eval / pdf is just the albedo
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let mut scatter_direction = rec.normal + Vec3::random_in_unit_sphere().normalized();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let direction = Onb::from_w(rec.normal).local(Vec3::cosine_direction(sampler.next_2d()));
+        let pdf = self.pdf(rec, direction, (-1.0) * r_in.direction().normalized());
+        if pdf <= 0.0 {
+            return None;
         }
 
-        let scattered = Ray::new(rec.p, scatter_direction, r_in.time());
-
-        Some((self.albedo.value(rec.u, rec.v, rec.p), scattered))
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time()),
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            lobe: Lobe::Diffuse { pdf },
+        })
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, _: Vec3) -> Color {
+        let cosine = rec.normal.dot(wi).max(0.0);
+        (cosine / PI) * self.albedo.value(rec.u, rec.v, rec.p)
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, _: Vec3) -> f64 {
+        rec.normal.dot(wi).max(0.0) / PI
     }
 }
 
@@ -43,33 +101,171 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    // The fuzz has no closed form density, so even rough metal is treated as specular
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let reflected = r_in.direction().reflect(rec.normal).normalized();
         let scattered = Ray::new(
             rec.p,
-            reflected + self.fuzz * Vec3::random_in_unit_sphere(),
+            reflected
+                + (self.fuzz * sampler.next_1d().cbrt()) * Vec3::uniform_sphere(sampler.next_2d()),
             r_in.time(),
         );
 
         if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
+            Some(ScatterRecord {
+                ray: scattered,
+                attenuation: self.albedo,
+                lobe: Lobe::Specular,
+            })
         } else {
             None
         }
     }
 }
-//
+
+// GGX microfacet reflection off a metal described by its complex index of refraction, with the
+// roughness in [0, 1] read from the mean of the channels of a texture
+pub struct Conductor<T: Texture> {
+    eta: Color,
+    k: Color,
+    roughness: T,
+}
+
+impl<T: Texture> Conductor<T> {
+    pub fn new(eta: Color, k: Color, roughness: T) -> Self {
+        Self { eta, k, roughness }
+    }
+    pub fn eta(&self) -> Color {
+        self.eta
+    }
+    pub fn k(&self) -> Color {
+        self.k
+    }
+
+    fn distribution(&self, rec: &HitRecord) -> Ggx {
+        let roughness = self.roughness.value(rec.u, rec.v, rec.p);
+        Ggx::from_roughness((roughness.x() + roughness.y() + roughness.z()) / 3.0)
+    }
+}
+
+// Measured indices at the red, green and blue wavelengths
+impl Conductor<SolidColor> {
+    pub fn gold(roughness: f64) -> Self {
+        Self::from_preset(
+            Color::new(0.143119, 0.374957, 1.44248),
+            Color::new(3.98316, 2.38572, 1.60322),
+            roughness,
+        )
+    }
+    pub fn copper(roughness: f64) -> Self {
+        Self::from_preset(
+            Color::new(0.200438, 0.924033, 1.10221),
+            Color::new(3.91295, 2.45285, 2.14219),
+            roughness,
+        )
+    }
+    pub fn aluminium(roughness: f64) -> Self {
+        Self::from_preset(
+            Color::new(1.65746, 0.880369, 0.521229),
+            Color::new(9.22387, 6.26952, 4.837),
+            roughness,
+        )
+    }
+    fn from_preset(eta: Color, k: Color, roughness: f64) -> Self {
+        Self::new(
+            eta,
+            k,
+            SolidColor::from_rbg(roughness, roughness, roughness),
+        )
+    }
+}
+
+// Samples the visible normals, so eval / pdf reduces to the Fresnel term times the shadowing of
+// the incoming direction. Paths reflected below the surface are lost, like for any single
+// scattering microfacet model
+impl<T: Texture> Material for Conductor<T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let uvw = Onb::from_w(rec.normal);
+        let wo = uvw.to_local((-1.0) * r_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let ggx = self.distribution(rec);
+        if ggx.is_specular() {
+            return Some(ScatterRecord {
+                ray: Ray::new(rec.p, r_in.direction().reflect(rec.normal), r_in.time()),
+                attenuation: fresnel_conductor(wo.z(), self.eta, self.k),
+                lobe: Lobe::Specular,
+            });
+        }
+
+        let h = ggx.sample_visible_normal(wo, sampler.next_2d());
+        let wi = ((-1.0) * wo).reflect(h);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let pdf = ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h));
+
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, uvw.local(wi), r_in.time()),
+            attenuation: (ggx.g2(wo, wi) / ggx.g1(wo))
+                * fresnel_conductor(wo.dot(h), self.eta, self.k),
+            lobe: Lobe::Diffuse { pdf },
+        })
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+        let uvw = Onb::from_w(rec.normal);
+        let (wi, wo) = (uvw.to_local(wi), uvw.to_local(wo));
+        let ggx = self.distribution(rec);
+        if ggx.is_specular() || wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let h = (wi + wo).normalized();
+        let fresnel = fresnel_conductor(wo.dot(h), self.eta, self.k);
+        (ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z())) * fresnel
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+        let uvw = Onb::from_w(rec.normal);
+        let (wi, wo) = (uvw.to_local(wi), uvw.to_local(wo));
+        let ggx = self.distribution(rec);
+        if ggx.is_specular() || wi.z() <= 0.0 || wo.z() <= 0.0 {
+            return 0.0;
+        }
+
+        let h = (wi + wo).normalized();
+        ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h))
+    }
+}
 
 pub struct Dielectric {
     ir: f64,
+    absorption: Color,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Dielectric {
         Dielectric {
             ir: index_of_refraction,
+            absorption: Color::new(0.0, 0.0, 0.0),
         }
     }
+    // Colored glass, leaving `tint` of the light after `distance` through it
+    pub fn with_tint(mut self, tint: Color, distance: f64) -> Self {
+        self.absorption = absorption(tint, distance);
+        self
+    }
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance
         let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
@@ -78,7 +274,12 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         let refraction_ratio = if rec.front_face {
             1.0 / self.ir
         } else {
@@ -89,9 +290,8 @@ impl Material for Dielectric {
         let cos_theta = ((-1.0) * unit_direction).dot(rec.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
 
-        let mut rng = rand::thread_rng();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let will_reflect = rng.gen::<f64>() < Self::reflectance(cos_theta, refraction_ratio);
+        let will_reflect = sampler.next_1d() < Self::reflectance(cos_theta, refraction_ratio);
 
         let direction = if cannot_refract || will_reflect {
             unit_direction.reflect(rec.normal)
@@ -99,10 +299,246 @@ impl Material for Dielectric {
             unit_direction.refract(rec.normal, refraction_ratio)
         };
 
-        let scattered = Ray::new(rec.p, direction, r_in.time());
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time()),
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            lobe: Lobe::Specular,
+        })
+    }
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        inside_transmittance(self.absorption, rec, distance)
+    }
+}
+
+// Frosted glass: GGX microfacet reflection and refraction (Walter et al. 2007) with the roughness
+// in [0, 1] read from the mean of the channels of a texture. Like `Dielectric` the refracted
+// radiance isn't scaled by the squared ratio of the indices, which cancels out going in and out
+// of a closed object
+pub struct RoughDielectric<T: Texture> {
+    ir: f64,
+    roughness: T,
+    absorption: Color,
+}
+
+impl<T: Texture> RoughDielectric<T> {
+    pub fn new(index_of_refraction: f64, roughness: T) -> Self {
+        Self {
+            ir: index_of_refraction,
+            roughness,
+            absorption: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+    // Colored glass, leaving `tint` of the light after `distance` through it
+    pub fn with_tint(mut self, tint: Color, distance: f64) -> Self {
+        self.absorption = absorption(tint, distance);
+        self
+    }
 
-        Some((Color::new(1.0, 1.0, 1.0), scattered))
+    fn distribution(&self, rec: &HitRecord) -> Ggx {
+        let roughness = self.roughness.value(rec.u, rec.v, rec.p);
+        Ggx::from_roughness((roughness.x() + roughness.y() + roughness.z()) / 3.0)
     }
+    // Index on the far side of the surface over the index on the side of the ray
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+
+    // Microfacet normal that turns `wo` into `wi` in the shading frame, facing `wo`, with the
+    // BSDF times cosine and the density of sampling `wi`
+    fn eval_pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> (f64, f64) {
+        let ggx = self.distribution(rec);
+        let onb = Onb::from_w(rec.normal);
+        let (wi, wo) = (onb.to_local(wi), onb.to_local(wo));
+        if wo.z() <= 0.0 || wi.z() == 0.0 || ggx.is_specular() {
+            return (0.0, 0.0);
+        }
+        let eta = self.eta(rec);
+
+        if wi.z() > 0.0 {
+            let h = (wi + wo).normalized();
+            let fresnel = fresnel_dielectric(wo.dot(h), eta);
+            let eval = ggx.d(h) * ggx.g2(wo, wi) * fresnel / (4.0 * wo.z());
+            let pdf = fresnel * ggx.visible_normal_pdf(wo, h) / (4.0 * wo.dot(h));
+            return (eval, pdf);
+        }
+
+        // Generalized half vector of the refraction
+        let mut h = (wo + eta * wi).normalized();
+        if h.z() < 0.0 {
+            h = (-1.0) * h;
+        }
+        let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+        if cos_o <= 0.0 || cos_i >= 0.0 {
+            return (0.0, 0.0);
+        }
+        let denom = (cos_o + eta * cos_i).powi(2);
+        let jacobian = eta * eta * cos_i.abs() / denom;
+        let transmitted = 1.0 - fresnel_dielectric(cos_o, eta);
+        let eval = ggx.d(h) * ggx.g2(wo, wi) * transmitted * cos_o * jacobian / wo.z();
+        let pdf = transmitted * ggx.visible_normal_pdf(wo, h) * jacobian;
+
+        (eval, pdf)
+    }
+}
+
+impl<T: Texture> Material for RoughDielectric<T> {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let ggx = self.distribution(rec);
+        let onb = Onb::from_w(rec.normal);
+        let wo = onb.to_local((-1.0) * r_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(rec);
+
+        if ggx.is_specular() {
+            let reflect = sampler.next_1d() < fresnel_dielectric(wo.z(), eta);
+            let n = Vec3::new(0.0, 0.0, 1.0);
+            let wi = if reflect {
+                ((-1.0) * wo).reflect(n)
+            } else {
+                ((-1.0) * wo).refract(n, 1.0 / eta)
+            };
+            return Some(ScatterRecord {
+                ray: Ray::new(rec.p, onb.local(wi), r_in.time()),
+                attenuation: Color::new(1.0, 1.0, 1.0),
+                lobe: Lobe::Specular,
+            });
+        }
+
+        // Reflect or refract off the visible normal by its Fresnel reflectance, which cancels
+        // out of eval / pdf like the distribution does
+        let h = ggx.sample_visible_normal(wo, sampler.next_2d());
+        let reflect = sampler.next_1d() < fresnel_dielectric(wo.dot(h), eta);
+        let wi = if reflect {
+            ((-1.0) * wo).reflect(h)
+        } else {
+            ((-1.0) * wo).refract(h, 1.0 / eta)
+        };
+        if reflect != (wi.z() > 0.0) || wi.z() == 0.0 {
+            return None;
+        }
+        let wi = wi.normalized();
+        let (eval, pdf) = self.eval_pdf(rec, onb.local(wi), onb.local(wo));
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, onb.local(wi), r_in.time()),
+            attenuation: Color::new(1.0, 1.0, 1.0) * (eval / pdf),
+            lobe: Lobe::Diffuse { pdf },
+        })
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+        let (eval, _) = self.eval_pdf(rec, wi, wo);
+        Color::new(eval, eval, eval)
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+        self.eval_pdf(rec, wi, wo).1
+    }
+    fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+        inside_transmittance(self.absorption, rec, distance)
+    }
+}
+
+// Glass with no inside like a window pane: light passes straight through without bending, and
+// the reflections bouncing between the two sides of the sheet are summed up
+pub struct ThinDielectric {
+    ir: f64,
+    tint: Color,
+}
+
+impl ThinDielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            ir: index_of_refraction,
+            tint: Color::new(1.0, 1.0, 1.0),
+        }
+    }
+    // Colored glass, leaving `tint` of the light going straight through the sheet, and less at
+    // grazing angles where the path through it is longer
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+}
+
+impl Material for ThinDielectric {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let unit_direction = r_in.direction().normalized();
+        let cos_i = ((-1.0) * unit_direction).dot(rec.normal).clamp(0.0, 1.0);
+        let r = fresnel_dielectric(cos_i, self.ir);
+        let t = 1.0 - r;
+        // Beer-Lambert along the refracted path through the sheet
+        let cos_t = (1.0 - (1.0 - cos_i * cos_i) / (self.ir * self.ir)).sqrt();
+        let channel = |tint: f64| {
+            let a = tint.clamp(0.0, 1.0).powf(1.0 / cos_t);
+            let bounces = 1.0 - r * r * a * a;
+            (r + t * t * r * a * a / bounces, t * t * a / bounces)
+        };
+        let (rx, tx) = channel(self.tint.x());
+        let (ry, ty) = channel(self.tint.y());
+        let (rz, tz) = channel(self.tint.z());
+        let reflectance = Color::new(rx, ry, rz);
+        let transmittance = Color::new(tx, ty, tz);
+
+        let total = rx + ry + rz + tx + ty + tz;
+        if total <= 0.0 {
+            return None;
+        }
+        let p_reflect = (rx + ry + rz) / total;
+        let (direction, attenuation) = if sampler.next_1d() < p_reflect {
+            (unit_direction.reflect(rec.normal), reflectance / p_reflect)
+        } else {
+            (unit_direction, transmittance / (1.0 - p_reflect))
+        };
+
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, direction, r_in.time()),
+            attenuation,
+            lobe: Lobe::Specular,
+        })
+    }
+}
+
+// Beer-Lambert absorption coefficient leaving `tint` of the light after `distance`
+fn absorption(tint: Color, distance: f64) -> Color {
+    let channel = |tint: f64| -tint.clamp(0.0, 1.0).ln() / distance;
+    Color::new(channel(tint.x()), channel(tint.y()), channel(tint.z()))
+}
+
+// Light left after `distance` inside a medium with `absorption`, none is absorbed outside
+fn inside_transmittance(absorption: Color, rec: &HitRecord, distance: f64) -> Color {
+    if rec.front_face {
+        return Color::new(1.0, 1.0, 1.0);
+    }
+    let channel = |absorption: f64| {
+        if absorption > 0.0 {
+            (-absorption * distance).exp()
+        } else {
+            1.0
+        }
+    };
+    Color::new(
+        channel(absorption.x()),
+        channel(absorption.y()),
+        channel(absorption.z()),
+    )
 }
 
 pub struct DiffuseLight<T: Texture> {
@@ -124,12 +560,15 @@ impl DiffuseLight<SolidColor> {
 }
 
 impl<T: Texture> Material for DiffuseLight<T> {
-    fn scatter(&self, _: &Ray, _: &HitRecord) -> Option<(Color, Ray)> {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> Option<ScatterRecord> {
         None
     }
     fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
         self.emit.value(u, v, point)
     }
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
 
 pub struct Isotropic<T: Texture> {
@@ -150,11 +589,26 @@ impl Isotropic<SolidColor> {
     }
 }
 
+// Uniform phase function, there is no surface so no cosine either
 impl<T: Texture> Material for Isotropic<T> {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let scattered = Ray::new(rec.p, Vec3::random_in_unit_sphere(), r_in.time());
-        let attenuation = self.albedo.value(rec.u, rec.v, rec.p);
-
-        Some((attenuation, scattered))
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, Vec3::uniform_sphere(sampler.next_2d()), r_in.time()),
+            attenuation: self.albedo.value(rec.u, rec.v, rec.p),
+            lobe: Lobe::Diffuse {
+                pdf: 1.0 / (4.0 * PI),
+            },
+        })
+    }
+    fn eval(&self, rec: &HitRecord, _: Vec3, _: Vec3) -> Color {
+        (1.0 / (4.0 * PI)) * self.albedo.value(rec.u, rec.v, rec.p)
+    }
+    fn pdf(&self, _: &HitRecord, _: Vec3, _: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
     }
 }
@@ -0,0 +1,130 @@
+use std::f64::consts::PI;
+
+use crate::{Color, Vec3};
+
+// Below this the distribution is too close to a mirror to sample and evaluate reliably, so
+// materials switch to a specular lobe
+pub(crate) const MIN_ALPHA: f64 = 1.0e-3;
+
+// Isotropic GGX (Trowbridge-Reitz) distribution of microfacet normals with the height
+// correlated Smith shadowing term. Directions are in the shading frame with the normal along +z
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // Perceptual roughness in [0, 1], squared so it looks linear
+    pub(crate) fn from_roughness(roughness: f64) -> Self {
+        Self {
+            alpha: roughness.clamp(0.0, 1.0).powi(2),
+        }
+    }
+    pub(crate) fn is_specular(&self) -> bool {
+        self.alpha < MIN_ALPHA
+    }
+
+    // Density of microfacet normals `h` per unit of projected area
+    pub(crate) fn d(&self, h: Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let denom = h.z() * h.z() * (a2 - 1.0) + 1.0;
+
+        a2 / (PI * denom * denom)
+    }
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+
+        0.5 * ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0)
+    }
+    // Fraction of the microfacets facing `w` that are visible from it
+    pub(crate) fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+    // Fraction visible from both `wo` and `wi`
+    pub(crate) fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Microfacet normal seen from `wo`, sampled with the distribution of visible normals
+    // (Heitz 2018), so `pdf` is `g1(wo) * |wo . h| * d(h) / wo.z`
+    pub(crate) fn sample_visible_normal(&self, wo: Vec3, (r1, r2): (f64, f64)) -> Vec3 {
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).normalized();
+
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        let r = r1.sqrt();
+        let phi = 2.0 * PI * r2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(0.0)).normalized()
+    }
+    // Density of `sample_visible_normal` picking `h`
+    pub(crate) fn visible_normal_pdf(&self, wo: Vec3, h: Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+}
+
+// Fresnel reflectance of a conductor with complex index of refraction `eta + i k` per channel,
+// for light arriving at `cos_theta` to the normal
+pub(crate) fn fresnel_conductor(cos_theta: f64, eta: Color, k: Color) -> Color {
+    let channel = |eta: f64, k: f64| {
+        let cos2 = cos_theta.clamp(0.0, 1.0).powi(2);
+        let sin2 = 1.0 - cos2;
+        let eta2 = eta * eta;
+        let k2 = k * k;
+
+        let t0 = eta2 - k2 - sin2;
+        let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+        let t1 = a2_plus_b2 + cos2;
+        let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+        let t2 = 2.0 * cos_theta * a;
+        let rs = (t1 - t2) / (t1 + t2);
+
+        let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+        let t4 = t2 * sin2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+
+        0.5 * (rp + rs)
+    };
+
+    Color::new(
+        channel(eta.x(), k.x()),
+        channel(eta.y(), k.y()),
+        channel(eta.z(), k.z()),
+    )
+}
+
+// Fresnel reflectance of a dielectric boundary for unpolarized light arriving at `cos_theta` to
+// the normal, `eta` is the index on the far side over the index on the near side
+pub(crate) fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let r_parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+
+    0.5 * (r_parallel * r_parallel + r_perpendicular * r_perpendicular)
+}
@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use crate::{Color, HitRecord, Material, Onb, Point3, Ray, Sampler, ScatterRecord, Texture, Vec3};
+
+// Tangent space normal map, with red along `tangent`, green along `bitangent` and blue along
+// the normal, each channel mapping [0, 1] to [-1, 1]. The texture is read as is, so image files
+// have to be stored without a gamma curve
+pub struct NormalMap<T: Texture> {
+    material: Arc<dyn Material>,
+    map: T,
+    // Scales the tilt, 0 leaves the surface flat
+    strength: f64,
+}
+
+impl<T: Texture> NormalMap<T> {
+    pub fn new(material: Arc<dyn Material>, map: T) -> Self {
+        Self {
+            material,
+            map,
+            strength: 1.0,
+        }
+    }
+    pub fn with_strength(mut self, strength: f64) -> Self {
+        self.strength = strength;
+        self
+    }
+
+    // Normal on the outside of the surface
+    fn perturbed(&self, rec: &HitRecord, outward: Vec3) -> Vec3 {
+        let m = 2.0 * self.map.value(rec.u, rec.v, rec.p) - Vec3::new(1.0, 1.0, 1.0);
+
+        // Gram-Schmidt, mirrored texture coordinates flip the bitangent
+        let tangent = rec.tangent - rec.tangent.dot(outward) * outward;
+        let tangent = if tangent.near_zero() {
+            Onb::from_w(outward).u()
+        } else {
+            tangent.normalized()
+        };
+        let mut bitangent = outward.cross(tangent);
+        if bitangent.dot(rec.bitangent) < 0.0 {
+            bitangent = (-1.0) * bitangent;
+        }
+
+        (self.strength * m.x()) * tangent + (self.strength * m.y()) * bitangent + m.z() * outward
+    }
+}
+
+// Height field `height` times `scale` in scene units, raising the surface along its normal.
+// The slope is taken over a small step in u and v, so the surface needs texture coordinates
+pub struct BumpMap<T: Texture> {
+    material: Arc<dyn Material>,
+    height: T,
+    scale: f64,
+}
+
+impl<T: Texture> BumpMap<T> {
+    pub fn new(material: Arc<dyn Material>, height: T, scale: f64) -> Self {
+        Self {
+            material,
+            height,
+            scale,
+        }
+    }
+
+    fn height(&self, u: f64, v: f64, point: Point3) -> f64 {
+        let height = self.height.value(u, v, point);
+        self.scale * (height.x() + height.y() + height.z()) / 3.0
+    }
+
+    // Normal on the outside of the surface
+    fn perturbed(&self, rec: &HitRecord, outward: Vec3) -> Vec3 {
+        const STEP: f64 = 5.0e-4;
+        if rec.tangent.near_zero() || rec.bitangent.near_zero() {
+            return outward;
+        }
+
+        let height = self.height(rec.u, rec.v, rec.p);
+        let du = (self.height(rec.u + STEP, rec.v, rec.p + STEP * rec.tangent) - height) / STEP;
+        let dv = (self.height(rec.u, rec.v + STEP, rec.p + STEP * rec.bitangent) - height) / STEP;
+
+        let normal = (rec.tangent + du * outward).cross(rec.bitangent + dv * outward);
+        if normal.dot(outward) < 0.0 {
+            (-1.0) * normal
+        } else {
+            normal
+        }
+    }
+}
+
+// Copy of `rec` with the shading normal `outward` on the outside, turned to the side of the ray
+fn shaded(rec: &HitRecord, outward: Vec3, wo: Vec3) -> HitRecord {
+    let outward = outward.normalized();
+    let normal = if rec.front_face {
+        outward
+    } else {
+        (-1.0) * outward
+    };
+
+    HitRecord {
+        p: rec.p,
+        t: rec.t,
+        mat: rec.mat.clone(),
+        normal: if normal.dot(wo) > 0.0 && !normal.near_zero() {
+            normal
+        } else {
+            rec.normal
+        },
+        front_face: rec.front_face,
+        u: rec.u,
+        v: rec.v,
+        tangent: rec.tangent,
+        bitangent: rec.bitangent,
+    }
+}
+
+fn outward(rec: &HitRecord) -> Vec3 {
+    if rec.front_face {
+        rec.normal
+    } else {
+        (-1.0) * rec.normal
+    }
+}
+
+// Forwards `Material` to the wrapped material with a copy of the hit whose shading normal is
+// tilted by `perturbed`. Where the tilt would put the viewer behind the surface the normal is
+// left alone
+macro_rules! shading_material {
+    ($name:ident) => {
+        impl<T: Texture> Material for $name<T> {
+            fn scatter(
+                &self,
+                r_in: &Ray,
+                rec: &HitRecord,
+                sampler: &mut dyn Sampler,
+            ) -> Option<ScatterRecord> {
+                let wo = (-1.0) * r_in.direction().normalized();
+                let rec = shaded(rec, self.perturbed(rec, outward(rec)), wo);
+                self.material.scatter(r_in, &rec, sampler)
+            }
+            fn emitted(&self, u: f64, v: f64, point: Point3) -> Color {
+                self.material.emitted(u, v, point)
+            }
+            fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+                let rec = shaded(rec, self.perturbed(rec, outward(rec)), wo);
+                self.material.eval(&rec, wi, wo)
+            }
+            fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+                let rec = shaded(rec, self.perturbed(rec, outward(rec)), wo);
+                self.material.pdf(&rec, wi, wo)
+            }
+            fn is_emissive(&self) -> bool {
+                self.material.is_emissive()
+            }
+            fn transmittance(&self, rec: &HitRecord, distance: f64) -> Color {
+                self.material.transmittance(rec, distance)
+            }
+        }
+    };
+}
+
+shading_material!(NormalMap);
+shading_material!(BumpMap);
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{
+    Color, DiffuseLight, ImageTexture, Material, MeshData, MeshFace, Point3, Principled,
+    SolidColor, Texture, TriangleMesh, Vec3,
+};
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: PathBuf,
+    // 1-based, None when the error isn't tied to a place in the file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:", self.path.display())?;
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+        }
+        write!(f, " {}", self.message)
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// Loads a Wavefront OBJ file as a single mesh. Polygons are split into triangle fans and
+// faces before any `usemtl` get `default_material`. Materials from `mtllib` files become
+// `DiffuseLight` when Ke > 0 and `Principled` otherwise:
+//
+//   base color     map_Kd or Kd, Ks for metals, Tf or white for glass
+//   metallic       map_Pm or Pm, else 1 with illum 3 or Ks > 0 and Kd = 0
+//   roughness      map_Pr or Pr, else from the exponent Ns, else smooth metal and glass
+//   transmission   1 with illum 4, 6 or 7 or dissolve d < 1, index of refraction Ni
+//   sheen, clearcoat and its roughness from the PBR extension Ps, Pc and Pcr
+pub fn load_obj(
+    path: impl AsRef<Path>,
+    default_material: Arc<dyn Material>,
+) -> Result<TriangleMesh, ObjError> {
+    load_obj_with_sources(path.as_ref(), default_material, &mut Vec::new())
+}
+
+// `load_obj` adding the paths of the OBJ, MTL and texture files it reads to `sources`
+pub(crate) fn load_obj_with_sources(
+    path: &Path,
+    default_material: Arc<dyn Material>,
+    sources: &mut Vec<PathBuf>,
+) -> Result<TriangleMesh, ObjError> {
+    let source = read(path)?;
+    sources.push(path.to_path_buf());
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut mesh = MeshData {
+        materials: vec![default_material],
+        ..MeshData::default()
+    };
+    let mut library: HashMap<String, Arc<dyn Material>> = HashMap::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut material = 0;
+
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: Some(index + 1),
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => mesh.positions.push(parse_vec3(&args).map_err(error)?),
+            "vn" => mesh.normals.push(parse_vec3(&args).map_err(error)?),
+            "vt" => {
+                let uv = parse_floats(&args, 1).map_err(error)?;
+                mesh.uvs.push((uv[0], uv.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(String::from("a face needs at least 3 vertices")));
+                }
+                let corners = args
+                    .iter()
+                    .map(|corner| parse_corner(corner, &mesh))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                for i in 1..corners.len() - 1 {
+                    let triangle = [corners[0], corners[i], corners[i + 1]];
+                    mesh.faces.push(MeshFace {
+                        vertices: triangle.map(|c| c.0),
+                        uvs: all_some(triangle.map(|c| c.1)),
+                        normals: all_some(triangle.map(|c| c.2)),
+                        material,
+                    });
+                }
+            }
+            "mtllib" => {
+                for file in args {
+                    library.extend(load_mtl(&base_dir.join(file), sources)?);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                material = match material_indices.get(&name) {
+                    Some(&index) => index,
+                    None => {
+                        let mat = library
+                            .get(&name)
+                            .ok_or_else(|| error(format!("unknown material `{}`", name)))?;
+                        mesh.materials.push(mat.clone());
+                        material_indices.insert(name, mesh.materials.len() - 1);
+                        mesh.materials.len() - 1
+                    }
+                };
+            }
+            // Groups, smoothing groups, lines, free-form geometry etc. don't affect the mesh
+            _ => {}
+        }
+    }
+
+    Ok(TriangleMesh::new(mesh))
+}
+
+#[derive(Default)]
+struct MtlMaterial {
+    kd: Option<Color>,
+    ks: Option<Color>,
+    ke: Option<Color>,
+    ns: Option<f64>,
+    ni: Option<f64>,
+    dissolve: Option<f64>,
+    illum: Option<u32>,
+    tf: Option<Color>,
+    pr: Option<f64>,
+    pm: Option<f64>,
+    ps: Option<f64>,
+    pc: Option<f64>,
+    pcr: Option<f64>,
+    map_kd: Option<PathBuf>,
+    map_pr: Option<PathBuf>,
+    map_pm: Option<PathBuf>,
+    // Where `newmtl` was, for errors
+    line: usize,
+}
+
+impl MtlMaterial {
+    fn to_material(&self) -> Result<Arc<dyn Material>, String> {
+        let is_black = |c: Option<Color>| c.is_none_or(|c| c.near_zero());
+        let solid = |value: f64| SolidColor::from_rbg(value, value, value);
+
+        if !is_black(self.ke) {
+            return Ok(Arc::new(DiffuseLight::from_color(self.ke.unwrap())));
+        }
+        let glass = matches!(self.illum, Some(4 | 6 | 7)) || self.dissolve.is_some_and(|d| d < 1.0);
+        let metal = self.illum == Some(3) || (!is_black(self.ks) && is_black(self.kd));
+
+        let mut material = if glass {
+            Principled::from_color(self.tf.unwrap_or(Color::new(1.0, 1.0, 1.0)))
+                .with_transmission(solid(1.0))
+        } else if metal && self.map_kd.is_none() {
+            Principled::from_color(self.ks.unwrap_or(Color::new(1.0, 1.0, 1.0)))
+        } else {
+            match &self.map_kd {
+                Some(map_kd) => Principled::new(open_texture(map_kd, true)?),
+                None => Principled::from_color(self.kd.unwrap_or(Color::new(0.8, 0.8, 0.8))),
+            }
+        };
+        material = material.with_ior(self.ni.unwrap_or(1.5));
+
+        match (&self.map_pm, self.pm) {
+            (Some(map_pm), _) => material = material.with_metallic(open_texture(map_pm, false)?),
+            (None, Some(pm)) => material = material.with_metallic(solid(pm)),
+            (None, None) if metal && !glass => material = material.with_metallic(solid(1.0)),
+            _ => {}
+        }
+        // Phong exponent to a GGX roughness, the square root of the width of the lobe
+        let roughness = match (self.pr, self.ns) {
+            (Some(pr), _) => Some(pr),
+            (None, Some(ns)) => Some((2.0 / (ns + 2.0)).sqrt().sqrt()),
+            (None, None) if metal || glass => Some(0.0),
+            (None, None) => None,
+        };
+        match (&self.map_pr, roughness) {
+            (Some(map_pr), _) => material = material.with_roughness(open_texture(map_pr, false)?),
+            (None, Some(roughness)) => material = material.with_roughness(solid(roughness)),
+            (None, None) => {}
+        }
+        if let Some(ps) = self.ps {
+            material = material.with_sheen(solid(ps));
+        }
+        if let Some(pc) = self.pc {
+            material = material.with_clearcoat(solid(pc));
+        }
+        if let Some(pcr) = self.pcr {
+            material = material.with_clearcoat_roughness(solid(pcr));
+        }
+
+        Ok(Arc::new(material))
+    }
+}
+
+// `srgb` for color maps, data maps are read as is
+fn open_texture(path: &Path, srgb: bool) -> Result<impl Texture, String> {
+    ImageTexture::open(path)
+        .map(|texture| texture.with_srgb(srgb))
+        .map_err(|err| format!("could not load texture `{}`: {}", path.display(), err))
+}
+
+fn load_mtl(
+    path: &Path,
+    sources: &mut Vec<PathBuf>,
+) -> Result<HashMap<String, Arc<dyn Material>>, ObjError> {
+    let source = read(path)?;
+    sources.push(path.to_path_buf());
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut parsed: Vec<(String, MtlMaterial)> = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError {
+            path: path.to_path_buf(),
+            line: Some(index + 1),
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            let material = MtlMaterial {
+                line: index + 1,
+                ..MtlMaterial::default()
+            };
+            parsed.push((args.join(" "), material));
+            continue;
+        }
+        let material = match parsed.last_mut() {
+            Some((_, material)) => material,
+            None => return Err(error(format!("`{}` before any `newmtl`", keyword))),
+        };
+
+        match keyword {
+            "Kd" => material.kd = Some(parse_vec3(&args).map_err(error)?),
+            "Ks" => material.ks = Some(parse_vec3(&args).map_err(error)?),
+            "Ke" => material.ke = Some(parse_vec3(&args).map_err(error)?),
+            "Ns" => material.ns = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Ni" => material.ni = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "d" => material.dissolve = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Tr" => material.dissolve = Some(1.0 - parse_floats(&args, 1).map_err(error)?[0]),
+            "illum" => {
+                let illum = args.first().and_then(|illum| illum.parse().ok());
+                material.illum = Some(illum.ok_or_else(|| error(String::from("invalid illum")))?);
+            }
+            "Tf" => material.tf = Some(parse_vec3(&args).map_err(error)?),
+            "Pr" => material.pr = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Pm" => material.pm = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Ps" => material.ps = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Pc" => material.pc = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            "Pcr" => material.pcr = Some(parse_floats(&args, 1).map_err(error)?[0]),
+            // Options like `-bm 1.0` come before the file name
+            "map_Kd" | "map_Pr" | "map_Pm" => {
+                let file = match args.last() {
+                    Some(file) => base_dir.join(file),
+                    None => return Err(error(String::from("missing texture file"))),
+                };
+                sources.push(file.clone());
+                match keyword {
+                    "map_Kd" => material.map_kd = Some(file),
+                    "map_Pr" => material.map_pr = Some(file),
+                    _ => material.map_pm = Some(file),
+                }
+            }
+            // Everything else has no counterpart in the existing materials
+            _ => {}
+        }
+    }
+
+    parsed
+        .into_iter()
+        .map(|(name, material)| {
+            let mat = material.to_material().map_err(|message| ObjError {
+                path: path.to_path_buf(),
+                line: Some(material.line),
+                message,
+            })?;
+            Ok((name, mat))
+        })
+        .collect()
+}
+
+fn read(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|err| ObjError {
+        path: path.to_path_buf(),
+        line: None,
+        message: err.to_string(),
+    })
+}
+
+fn parse_floats(args: &[&str], min_count: usize) -> Result<Vec<f64>, String> {
+    if args.len() < min_count {
+        return Err(format!(
+            "expected {} numbers, found {}",
+            min_count,
+            args.len()
+        ));
+    }
+    args.iter()
+        .map(|arg| arg.parse().map_err(|_| format!("invalid number `{}`", arg)))
+        .collect()
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+    let v = parse_floats(args, 3)?;
+    Ok(Point3::new(v[0], v[1], v[2]))
+}
+
+// OBJ indices start at 1, negative ones count back from the last element so far
+fn parse_index(index: &str, len: usize, what: &str) -> Result<usize, String> {
+    let i: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid {} index `{}`", what, index))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{} index {} out of range", what, i));
+    }
+
+    Ok(resolved as usize)
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_corner(
+    corner: &str,
+    mesh: &MeshData,
+) -> Result<(usize, Option<usize>, Option<usize>), String> {
+    let mut parts = corner.split('/');
+    let vertex = parse_index(parts.next().unwrap_or(""), mesh.positions.len(), "vertex")?;
+    let uv = match parts.next() {
+        Some(uv) if !uv.is_empty() => Some(parse_index(uv, mesh.uvs.len(), "texture")?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(normal) if !normal.is_empty() => {
+            Some(parse_index(normal, mesh.normals.len(), "normal")?)
+        }
+        _ => None,
+    };
+
+    Ok((vertex, uv, normal))
+}
+
+fn all_some(indices: [Option<usize>; 3]) -> Option<[usize; 3]> {
+    Some([indices[0]?, indices[1]?, indices[2]?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{Hitable, Lambertian, Pcg32, Ray};
+
+    static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+    // Writes the files into a directory of their own and loads `mesh.obj`
+    fn load(files: &[(&str, &str)]) -> (Result<TriangleMesh, ObjError>, Arc<dyn Material>) {
+        let dir = std::env::temp_dir().join(format!(
+            "obj-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+        let default: Arc<dyn Material> =
+            Arc::new(Lambertian::new(SolidColor::from_rbg(0.5, 0.5, 0.5)));
+        let mesh = load_obj(dir.join("mesh.obj"), default.clone());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        (mesh, default)
+    }
+
+    const MESH: &str = "\
+# A triangle with the default material and a quad with a light next to it
+mtllib lights.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+v 2 0 0
+v 4 0 0
+v 4 1 0
+v 2 1 0
+vn 0 0 1
+f 1//1 2//1 3//1
+usemtl lamp
+f -4 -3 -2 -1
+";
+
+    #[test]
+    fn loads_polygons_with_their_materials() {
+        let lights = "newmtl lamp\nKe 4 4 4\n";
+        let (mesh, default) = load(&[("mesh.obj", MESH), ("lights.mtl", lights)]);
+        let mesh = mesh.unwrap();
+        let mut rng = Pcg32::new(0, 0);
+        let mut hit = |x: f64, y: f64| {
+            let r = Ray::new(Point3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0), 0.0);
+            mesh.hit(&r, 0.001, f64::INFINITY, &mut rng)
+        };
+
+        let rec = hit(0.25, 0.25).unwrap();
+        assert!(Arc::ptr_eq(&rec.mat, &default));
+        assert!((rec.t - 1.0).abs() < 1.0e-12);
+        // Both triangles of the fan the quad is split into
+        for (x, y) in [(3.9, 0.1), (2.1, 0.9)] {
+            let rec = hit(x, y).unwrap();
+            assert!(!Arc::ptr_eq(&rec.mat, &default));
+        }
+        assert!(hit(1.0, 0.9).is_none());
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let cases = [
+            (
+                MESH.replace("f 1//1", "f 1//2"),
+                11,
+                "normal index 2 out of range",
+            ),
+            (
+                MESH.replace("usemtl lamp", "usemtl lump"),
+                12,
+                "unknown material `lump`",
+            ),
+            (MESH.replace("v 4 1 0", "v 4 one 0"), 8, "`one`"),
+        ];
+        for (source, line, message) in cases {
+            let lights = "newmtl lamp\nKe 4 4 4\n";
+            let (mesh, _) = load(&[("mesh.obj", &source), ("lights.mtl", lights)]);
+            let err = mesh.err().unwrap();
+            assert_eq!(err.line, Some(line), "{}", err);
+            assert!(err.message.contains(message), "{}", err);
+        }
+
+        let (mesh, _) = load(&[
+            ("mesh.obj", MESH),
+            ("lights.mtl", "newmtl lamp\nKe 4 x 4\n"),
+        ]);
+        let err = mesh.err().unwrap();
+        assert_eq!(err.path.file_name().unwrap(), "lights.mtl");
+        assert_eq!(err.line, Some(2), "{}", err);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::Vec3;
+
+// Orthonormal basis around `w`, for directions sampled relative to a normal or an axis
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(w: Vec3) -> Self {
+        let w = w.normalized();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalized();
+        let u = w.cross(v);
+
+        Self { u, v, w }
+    }
+    pub fn u(&self) -> Vec3 {
+        self.u
+    }
+    pub fn v(&self) -> Vec3 {
+        self.v
+    }
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+    // From coordinates in this basis to world space
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x() * self.u + a.y() * self.v + a.z() * self.w
+    }
+    // From world space to coordinates in this basis
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
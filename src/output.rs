@@ -0,0 +1,190 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use image::codecs::hdr::HdrEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::openexr::OpenExrEncoder;
+use image::codecs::png::PngEncoder;
+use image::codecs::pnm::{PnmEncoder, PnmSubtype, SampleEncoding};
+use image::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use image::{ColorType, ImageEncoder, ImageError, ImageResult, Rgb};
+
+use crate::{Color, ToneMapping};
+
+const JPEG_QUALITY: u8 = 90;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    // Binary P6
+    Ppm,
+    // Linear float formats, written without tone mapping
+    Hdr,
+    Exr,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "ppm" => Some(ImageFormat::Ppm),
+            "hdr" => Some(ImageFormat::Hdr),
+            "exr" => Some(ImageFormat::Exr),
+            _ => None,
+        }
+    }
+}
+
+// Linear colors of the rendered image, stored row by row starting at the top left
+pub struct Framebuffer {
+    width: u64,
+    height: u64,
+    pixels: Vec<Color>,
+    // Used for the 8 bit formats
+    tone_mapping: ToneMapping,
+}
+
+impl Framebuffer {
+    pub fn new(width: u64, height: u64) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::new(0.0, 0.0, 0.0); (width * height) as usize],
+            tone_mapping: ToneMapping::default(),
+        }
+    }
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+    pub fn get(&self, x: u64, y: u64) -> Color {
+        self.pixels[(y * self.width + x) as usize]
+    }
+    pub fn set(&mut self, x: u64, y: u64, color: Color) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+    // Row `y` of the image, counted from the top
+    pub fn row_mut(&mut self, y: u64) -> &mut [Color] {
+        let start = (y * self.width) as usize;
+        &mut self.pixels[start..start + self.width as usize]
+    }
+
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|&c| self.tone_mapping.to_rgb8(c))
+            .collect()
+    }
+    pub fn to_rgb32f(&self) -> Vec<Rgb<f32>> {
+        self.pixels
+            .iter()
+            .map(|c| Rgb([c.x() as f32, c.y() as f32, c.z() as f32]))
+            .collect()
+    }
+
+    // ASCII P3, kept for piping to stdout
+    pub fn write_p3(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "P3")?;
+        writeln!(out, "{} {}", self.width, self.height)?;
+        writeln!(out, "255")?;
+        for &pixel in &self.pixels {
+            let [r, g, b] = self.tone_mapping.to_rgb8(pixel);
+            writeln!(out, "{} {} {}", r, g, b)?;
+        }
+        out.flush()
+    }
+
+    // Picks the image format from the extension of `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> ImageResult<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            let hint = match path.extension() {
+                Some(ext) => ImageFormatHint::PathExtension(ext.into()),
+                None => ImageFormatHint::Unknown,
+            };
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                hint.clone(),
+                UnsupportedErrorKind::Format(hint),
+            ))
+        })?;
+
+        self.save_with_format(path, format)
+    }
+
+    pub fn save_with_format(&self, path: impl AsRef<Path>, format: ImageFormat) -> ImageResult<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let (w, h) = (self.width as u32, self.height as u32);
+
+        match format {
+            ImageFormat::Png => {
+                PngEncoder::new(&mut out).write_image(&self.to_rgb8(), w, h, ColorType::Rgb8)?
+            }
+            ImageFormat::Jpeg => JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY)
+                .write_image(&self.to_rgb8(), w, h, ColorType::Rgb8)?,
+            ImageFormat::Ppm => PnmEncoder::new(&mut out)
+                .with_subtype(PnmSubtype::Pixmap(SampleEncoding::Binary))
+                .write_image(&self.to_rgb8(), w, h, ColorType::Rgb8)?,
+            ImageFormat::Hdr => HdrEncoder::new(&mut out).encode(
+                &self.to_rgb32f(),
+                self.width as usize,
+                self.height as usize,
+            )?,
+            ImageFormat::Exr => {
+                let data: Vec<u8> = self
+                    .to_rgb32f()
+                    .iter()
+                    .flat_map(|p| p.0)
+                    .flat_map(f32::to_ne_bytes)
+                    .collect();
+                OpenExrEncoder::new(&mut out).write_image(&data, w, h, ColorType::Rgb32F)?
+            }
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Film, Filter, FilterKind, ToneMapper};
+
+    #[test]
+    fn exr_holds_the_film_untouched() {
+        let mut film = Film::new(6, 4, Filter::new(FilterKind::Gaussian));
+        for y in 0..16 {
+            for x in 0..24 {
+                let (x, y) = (x as f64 / 4.0 + 0.125, y as f64 / 4.0 + 0.125);
+                film.add_sample(x, y, Color::new(x, y, 4.0 * x * y));
+            }
+        }
+        let mut framebuffer = film.to_framebuffer();
+        framebuffer.set_tone_mapping(ToneMapping::new(ToneMapper::Aces, 2.0));
+
+        let path = std::env::temp_dir().join(format!("film-{}.exr", std::process::id()));
+        framebuffer.save(&path).unwrap();
+        let read = image::open(&path).map(|image| image.into_rgb32f());
+        std::fs::remove_file(&path).unwrap();
+
+        let read = read.unwrap();
+        assert_eq!(read.dimensions(), (6, 4));
+        let read: Vec<Rgb<f32>> = read.pixels().copied().collect();
+        assert_eq!(read, framebuffer.to_rgb32f());
+    }
+}
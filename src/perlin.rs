@@ -1,6 +1,6 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 
-use crate::{Point3, Vec3};
+use crate::{Pcg32, Point3, Vec3};
 
 pub struct Perlin {
     rand_vec: Vec<Vec3>,
@@ -12,17 +12,16 @@ pub struct Perlin {
 impl Perlin {
     const POINT_COUNT: usize = 256;
 
-    fn permute(p: &mut [usize]) {
-        let mut rng = rand::thread_rng();
+    fn permute(p: &mut [usize], rng: &mut dyn RngCore) {
         for i in (0..(p.len() - 1)).rev() {
             let target = rng.gen_range(0..=i);
             p.swap(target, i);
         }
     }
 
-    fn perlin_generate_perm() -> Vec<usize> {
+    fn perlin_generate_perm(rng: &mut dyn RngCore) -> Vec<usize> {
         let mut p: Vec<usize> = (0..Self::POINT_COUNT).collect();
-        Self::permute(&mut p);
+        Self::permute(&mut p, rng);
         p
     }
     // fn trilinear_intp(c: [[[f64; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
@@ -44,32 +43,37 @@ impl Perlin {
         let vv = v * v * (3.0 - 2.0 * v);
         let ww = w * w * (3.0 - 2.0 * w);
         let mut accum = 0.0;
-        for i in 0..2 {
-            for j in 0..2 {
-                for k in 0..2 {
-                    let weight = Vec3::new(u - i as f64, v - j as f64, w - k as f64);
-                    accum += (i as f64 * uu + (1 - i) as f64 * (1.0 - uu))
-                        * (j as f64 * vv + (1 - j) as f64 * (1.0 - vv))
-                        * (k as f64 * ww + (1 - k) as f64 * (1.0 - ww))
-                        * c[i][j][k].dot(weight);
+        for (i, ci) in c.iter().enumerate() {
+            for (j, cij) in ci.iter().enumerate() {
+                for (k, cijk) in cij.iter().enumerate() {
+                    let (i, j, k) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(u - i, v - j, w - k);
+                    accum += (i * uu + (1.0 - i) * (1.0 - uu))
+                        * (j * vv + (1.0 - j) * (1.0 - vv))
+                        * (k * ww + (1.0 - k) * (1.0 - ww))
+                        * cijk.dot(weight);
                 }
             }
         }
         accum
     }
 
+    // Always the same noise, so textures don't change between renders
     pub fn new() -> Self {
+        Self::with_rng(&mut Pcg32::new(0, 0))
+    }
+    pub fn with_rng(rng: &mut dyn RngCore) -> Self {
         let mut rand_float = Vec::with_capacity(Self::POINT_COUNT);
         for _ in 0..Self::POINT_COUNT {
-            rand_float.push(Vec3::random(-1.0..1.0).normalized());
+            rand_float.push(Vec3::random(-1.0..1.0, rng).normalized());
         }
 
-        return Self {
+        Self {
             rand_vec: rand_float,
-            perm_x: Self::perlin_generate_perm(),
-            perm_y: Self::perlin_generate_perm(),
-            perm_z: Self::perlin_generate_perm(),
-        };
+            perm_x: Self::perlin_generate_perm(rng),
+            perm_y: Self::perlin_generate_perm(rng),
+            perm_z: Self::perlin_generate_perm(rng),
+        }
     }
     pub fn noise(&self, p: &Vec3) -> f64 {
         let u = p.x() - p.x().floor();
@@ -81,12 +85,12 @@ impl Perlin {
         let k = p.z().floor() as usize;
         let mut c = [[[Vec3::new(0.0, 0.0, 0.0); 2]; 2]; 2];
 
-        for di in 0..2 {
-            for dj in 0..2 {
-                for dk in 0..2 {
-                    c[di][dj][dk] = self.rand_vec[self.perm_x[((i + di) & 255)]
-                        ^ self.perm_y[((j + dj) & 255)]
-                        ^ self.perm_z[((k + dk) & 255)]];
+        for (di, c_i) in c.iter_mut().enumerate() {
+            for (dj, c_ij) in c_i.iter_mut().enumerate() {
+                for (dk, c_ijk) in c_ij.iter_mut().enumerate() {
+                    *c_ijk = self.rand_vec[self.perm_x[(i + di) & 255]
+                        ^ self.perm_y[(j + dj) & 255]
+                        ^ self.perm_z[(k + dk) & 255]];
                 }
             }
         }
@@ -99,10 +103,16 @@ impl Perlin {
         let mut temp_p = point;
         let mut weight = 1.0;
         for _ in 0..depth {
-            accum += weight * Self::noise(&self, &temp_p);
+            accum += weight * self.noise(&temp_p);
             weight *= 0.5;
             temp_p *= 2.0;
         }
         accum.abs()
     }
 }
+
+impl Default for Perlin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
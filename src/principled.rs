@@ -0,0 +1,332 @@
+use std::f64::consts::PI;
+
+use crate::microfacet::{Ggx, MIN_ALPHA};
+use crate::tonemap::luminance;
+use crate::{
+    Color, HitRecord, Lobe, Material, Onb, Ray, Sampler, ScatterRecord, SolidColor, Texture, Vec3,
+};
+
+// Principled BSDF after Burley's Disney model: a diffuse base with retro-reflection, a subsurface
+// approximation and sheen, GGX reflection that takes the base color as it gets metallic, GGX
+// transmission and a clearcoat layer on top. Every parameter is read from a texture, the scalar
+// ones from the mean of its channels. The lobes are picked at random by their expected
+// contribution and combined with multiple importance sampling
+pub struct Principled {
+    base_color: Box<dyn Texture>,
+    metallic: Box<dyn Texture>,
+    roughness: Box<dyn Texture>,
+    // Scales the reflectance of the dielectric part, 0.5 gives the one of `ir`
+    specular: Box<dyn Texture>,
+    sheen: Box<dyn Texture>,
+    clearcoat: Box<dyn Texture>,
+    clearcoat_roughness: Box<dyn Texture>,
+    transmission: Box<dyn Texture>,
+    subsurface: Box<dyn Texture>,
+    ir: f64,
+}
+
+// The parameters at a hit, with `eta` the index on the far side over the one on the side of the
+// ray
+struct Parameters {
+    base_color: Color,
+    metallic: f64,
+    roughness: f64,
+    specular: Ggx,
+    f0: f64,
+    sheen: f64,
+    clearcoat: f64,
+    clearcoat_ggx: Ggx,
+    transmission: f64,
+    subsurface: f64,
+    eta: f64,
+}
+
+// Index of each lobe in the sampling probabilities
+const DIFFUSE: usize = 0;
+const SPECULAR: usize = 1;
+const TRANSMISSION: usize = 2;
+const CLEARCOAT: usize = 3;
+
+fn solid(value: f64) -> Box<dyn Texture> {
+    Box::new(SolidColor::from_rbg(value, value, value))
+}
+
+fn scalar(texture: &dyn Texture, rec: &HitRecord) -> f64 {
+    let value = texture.value(rec.u, rec.v, rec.p);
+    ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+}
+
+impl Principled {
+    // Rough plastic of `base_color`, the other parameters start at 0 except for `roughness` and
+    // `specular` at 0.5 and a clearcoat roughness of 0.03
+    pub fn new(base_color: impl Texture + 'static) -> Self {
+        Self {
+            base_color: Box::new(base_color),
+            metallic: solid(0.0),
+            roughness: solid(0.5),
+            specular: solid(0.5),
+            sheen: solid(0.0),
+            clearcoat: solid(0.0),
+            clearcoat_roughness: solid(0.03),
+            transmission: solid(0.0),
+            subsurface: solid(0.0),
+            ir: 1.5,
+        }
+    }
+    pub fn from_color(color: Color) -> Self {
+        Self::new(SolidColor::new(color))
+    }
+
+    pub fn with_metallic(mut self, metallic: impl Texture + 'static) -> Self {
+        self.metallic = Box::new(metallic);
+        self
+    }
+    pub fn with_roughness(mut self, roughness: impl Texture + 'static) -> Self {
+        self.roughness = Box::new(roughness);
+        self
+    }
+    pub fn with_specular(mut self, specular: impl Texture + 'static) -> Self {
+        self.specular = Box::new(specular);
+        self
+    }
+    pub fn with_sheen(mut self, sheen: impl Texture + 'static) -> Self {
+        self.sheen = Box::new(sheen);
+        self
+    }
+    pub fn with_clearcoat(mut self, clearcoat: impl Texture + 'static) -> Self {
+        self.clearcoat = Box::new(clearcoat);
+        self
+    }
+    pub fn with_clearcoat_roughness(mut self, roughness: impl Texture + 'static) -> Self {
+        self.clearcoat_roughness = Box::new(roughness);
+        self
+    }
+    pub fn with_transmission(mut self, transmission: impl Texture + 'static) -> Self {
+        self.transmission = Box::new(transmission);
+        self
+    }
+    pub fn with_subsurface(mut self, subsurface: impl Texture + 'static) -> Self {
+        self.subsurface = Box::new(subsurface);
+        self
+    }
+    pub fn with_ior(mut self, index_of_refraction: f64) -> Self {
+        self.ir = index_of_refraction;
+        self
+    }
+
+    fn parameters(&self, rec: &HitRecord) -> Parameters {
+        // A perfect mirror can't be mixed with the other lobes, so the roughness stops just short
+        let min_roughness = MIN_ALPHA.sqrt();
+        let roughness = scalar(self.roughness.as_ref(), rec).max(min_roughness);
+        let clearcoat_roughness = scalar(self.clearcoat_roughness.as_ref(), rec).max(min_roughness);
+        let r0 = ((self.ir - 1.0) / (self.ir + 1.0)).powi(2);
+
+        Parameters {
+            base_color: self.base_color.value(rec.u, rec.v, rec.p),
+            metallic: scalar(self.metallic.as_ref(), rec),
+            roughness,
+            specular: Ggx::from_roughness(roughness),
+            f0: (2.0 * r0 * scalar(self.specular.as_ref(), rec)).min(1.0),
+            sheen: scalar(self.sheen.as_ref(), rec),
+            clearcoat: scalar(self.clearcoat.as_ref(), rec),
+            clearcoat_ggx: Ggx::from_roughness(clearcoat_roughness),
+            transmission: scalar(self.transmission.as_ref(), rec),
+            subsurface: scalar(self.subsurface.as_ref(), rec),
+            eta: if rec.front_face {
+                self.ir
+            } else {
+                1.0 / self.ir
+            },
+        }
+    }
+}
+
+impl Parameters {
+    // Schlick's approximation of the dielectric reflectance, taken with the angle on the
+    // denser side so light inside the object is totally reflected past the critical angle
+    fn dielectric_fresnel(&self, cos_theta: f64) -> f64 {
+        let cos = if self.eta < 1.0 {
+            let sin2_t = (1.0 - cos_theta * cos_theta) / (self.eta * self.eta);
+            if sin2_t >= 1.0 {
+                return 1.0;
+            }
+            (1.0 - sin2_t).sqrt()
+        } else {
+            cos_theta
+        };
+        self.f0 + (1.0 - self.f0) * schlick_weight(cos)
+    }
+    // Reflectance of the specular lobe, from the dielectric one to the base color of a metal
+    fn fresnel(&self, cos_theta: f64) -> Color {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let metal = self.base_color + schlick_weight(cos_theta) * (white - self.base_color);
+        let dielectric = self.dielectric_fresnel(cos_theta);
+
+        (1.0 - self.metallic) * Color::new(dielectric, dielectric, dielectric)
+            + self.metallic * metal
+    }
+
+    // Chance of sampling each lobe, in proportion to roughly how much light it reflects
+    fn lobe_probabilities(&self, wo: Vec3) -> [f64; 4] {
+        let dielectric = 1.0 - self.metallic;
+        let mut weights = [0.0; 4];
+        weights[DIFFUSE] = dielectric * (1.0 - self.transmission);
+        weights[SPECULAR] = luminance(self.fresnel(wo.z()));
+        weights[TRANSMISSION] =
+            dielectric * self.transmission * (1.0 - self.dielectric_fresnel(wo.z()));
+        weights[CLEARCOAT] = 0.25 * self.clearcoat * (0.04 + 0.96 * schlick_weight(wo.z()));
+
+        let total: f64 = weights.iter().sum();
+        if total > 0.0 {
+            weights.map(|weight| weight / total)
+        } else {
+            weights
+        }
+    }
+
+    // BSDF times cosine and the density of sampling `wi`, in the shading frame with `wo` above
+    fn eval_pdf(&self, wi: Vec3, wo: Vec3) -> (Color, f64) {
+        let black = Color::new(0.0, 0.0, 0.0);
+        if wo.z() <= 0.0 || wi.z() == 0.0 {
+            return (black, 0.0);
+        }
+        let probabilities = self.lobe_probabilities(wo);
+        let dielectric = 1.0 - self.metallic;
+
+        if wi.z() < 0.0 {
+            let weight = dielectric * self.transmission;
+            if weight <= 0.0 {
+                return (black, 0.0);
+            }
+            // Generalized half vector of the refraction, like `RoughDielectric`
+            let mut h = (wo + self.eta * wi).normalized();
+            if h.z() < 0.0 {
+                h = (-1.0) * h;
+            }
+            let (cos_o, cos_i) = (wo.dot(h), wi.dot(h));
+            if cos_o <= 0.0 || cos_i >= 0.0 {
+                return (black, 0.0);
+            }
+            let ggx = self.specular;
+            let jacobian = self.eta * self.eta * cos_i.abs() / (cos_o + self.eta * cos_i).powi(2);
+            let transmitted = 1.0 - self.dielectric_fresnel(cos_o);
+            let eval = weight * ggx.d(h) * ggx.g2(wo, wi) * transmitted * cos_o * jacobian / wo.z();
+            let pdf = probabilities[TRANSMISSION] * ggx.visible_normal_pdf(wo, h) * jacobian;
+            return (eval * self.base_color, pdf);
+        }
+
+        let h = (wi + wo).normalized();
+        let cos_d = wi.dot(h);
+        let mut eval = black;
+        let mut pdf = 0.0;
+
+        // Burley's diffuse and its Hanrahan-Krueger style flattening for subsurface scattering,
+        // plus sheen at grazing angles tinted halfway to the hue of the base color
+        let weight = dielectric * (1.0 - self.transmission);
+        if weight > 0.0 {
+            let (fl, fv) = (schlick_weight(wi.z()), schlick_weight(wo.z()));
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+            let fd = (1.0 + (fd90 - 1.0) * fl) * (1.0 + (fd90 - 1.0) * fv);
+            let fss90 = self.roughness * cos_d * cos_d;
+            let fss = (1.0 + (fss90 - 1.0) * fl) * (1.0 + (fss90 - 1.0) * fv);
+            let ss = 1.25 * (fss * (1.0 / (wi.z() + wo.z()) - 0.5) + 0.5);
+            let diffuse = (fd + (ss - fd) * self.subsurface) / PI;
+
+            let lum = luminance(self.base_color);
+            let tint = if lum > 0.0 {
+                self.base_color / lum
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            let sheen_color = 0.5 * (Color::new(1.0, 1.0, 1.0) + tint);
+            let sheen = self.sheen * schlick_weight(cos_d);
+
+            eval += (weight * wi.z()) * (diffuse * self.base_color + sheen * sheen_color);
+            pdf += probabilities[DIFFUSE] * wi.z() / PI;
+        }
+
+        let ggx = self.specular;
+        eval += (ggx.d(h) * ggx.g2(wo, wi) / (4.0 * wo.z())) * self.fresnel(cos_d);
+        pdf += probabilities[SPECULAR] * ggx.visible_normal_pdf(wo, h) / (4.0 * cos_d);
+
+        if self.clearcoat > 0.0 {
+            let ggx = self.clearcoat_ggx;
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let coat = 0.25 * self.clearcoat * ggx.d(h) * ggx.g2(wo, wi) * fresnel / (4.0 * wo.z());
+            eval += Color::new(coat, coat, coat);
+            pdf += probabilities[CLEARCOAT] * ggx.visible_normal_pdf(wo, h) / (4.0 * cos_d);
+        }
+
+        (eval, pdf)
+    }
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+impl Material for Principled {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let params = self.parameters(rec);
+        let onb = Onb::from_w(rec.normal);
+        let wo = onb.to_local((-1.0) * r_in.direction().normalized());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let probabilities = params.lobe_probabilities(wo);
+        let choice = sampler.next_1d();
+        let r = sampler.next_2d();
+        let mut lobe = 0;
+        let mut sum = probabilities[0];
+        while lobe + 1 < probabilities.len() && choice >= sum {
+            lobe += 1;
+            sum += probabilities[lobe];
+        }
+
+        let wi = match lobe {
+            DIFFUSE => Vec3::cosine_direction(r),
+            SPECULAR => ((-1.0) * wo).reflect(params.specular.sample_visible_normal(wo, r)),
+            CLEARCOAT => ((-1.0) * wo).reflect(params.clearcoat_ggx.sample_visible_normal(wo, r)),
+            _ => {
+                let h = params.specular.sample_visible_normal(wo, r);
+                let cos_o = wo.dot(h);
+                if 1.0 - cos_o * cos_o >= params.eta * params.eta {
+                    return None;
+                }
+                ((-1.0) * wo).refract(h, 1.0 / params.eta)
+            }
+        };
+        if wi.z() == 0.0 {
+            return None;
+        }
+        let wi = wi.normalized();
+        let (eval, pdf) = params.eval_pdf(wi, wo);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some(ScatterRecord {
+            ray: Ray::new(rec.p, onb.local(wi), r_in.time()),
+            attenuation: eval / pdf,
+            lobe: Lobe::Diffuse { pdf },
+        })
+    }
+    fn eval(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> Color {
+        let onb = Onb::from_w(rec.normal);
+        self.parameters(rec)
+            .eval_pdf(onb.to_local(wi), onb.to_local(wo))
+            .0
+    }
+    fn pdf(&self, rec: &HitRecord, wi: Vec3, wo: Vec3) -> f64 {
+        let onb = Onb::from_w(rec.normal);
+        self.parameters(rec)
+            .eval_pdf(onb.to_local(wi), onb.to_local(wo))
+            .1
+    }
+}
@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressStats {
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    // Every ray traced so far, camera rays as well as scattered ones
+    pub rays: u64,
+    pub elapsed: Duration,
+}
+
+impl ProgressStats {
+    pub fn fraction(&self) -> f64 {
+        if self.tiles_total == 0 {
+            return 1.0;
+        }
+        self.tiles_done as f64 / self.tiles_total as f64
+    }
+    // Assumes the remaining tiles take as long as the finished ones on average
+    pub fn eta(&self) -> Option<Duration> {
+        if self.tiles_done == 0 {
+            return None;
+        }
+        let remaining = (self.tiles_total - self.tiles_done) as f64 / self.tiles_done as f64;
+        Some(self.elapsed.mul_f64(remaining))
+    }
+    pub fn rays_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return 0.0;
+        }
+        self.rays as f64 / secs
+    }
+}
+
+// Called from the render threads, so tiles can be reported in any order
+pub trait Progress: Sync {
+    fn tile_done(&self, stats: &ProgressStats);
+    fn finish(&self, _stats: &ProgressStats) {}
+}
+
+// No progress reporting
+impl Progress for () {
+    fn tile_done(&self, _: &ProgressStats) {}
+}
+
+pub struct StderrProgress;
+
+impl Progress for StderrProgress {
+    fn tile_done(&self, stats: &ProgressStats) {
+        let eta = match stats.eta() {
+            Some(eta) => format_duration(eta),
+            None => String::from("--:--:--"),
+        };
+        eprint!(
+            "\rTiles: {}/{} ({:5.1}%), ETA {}, {:.2} Mrays/s  ",
+            stats.tiles_done,
+            stats.tiles_total,
+            100.0 * stats.fraction(),
+            eta,
+            stats.rays_per_sec() / 1.0e6
+        );
+    }
+    fn finish(&self, stats: &ProgressStats) {
+        eprintln!(
+            "\nDone in {}, {:.2} Mrays/s.",
+            format_duration(stats.elapsed),
+            stats.rays_per_sec() / 1.0e6
+        );
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
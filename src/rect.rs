@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use crate::{HitRecord, Hitable, Material, Point3, Vec3, AABB};
+use rand::RngCore;
+
+use crate::{HitRecord, Hitable, Material, Point3, Ray, Sampler, Vec3, AABB};
 
 pub enum PlaneOrientation {
     XY,
@@ -44,6 +46,23 @@ impl Plane {
             PlaneOrientation::ZX => (1, 2, 0),
         }
     }
+    // `t` and the in-plane coordinates where `r` crosses the rectangle
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+        let (k_axis, x_axis, y_axis) = self.get_orientation_axis();
+
+        let t = (self.k - r.origin()[k_axis]) / r.direction()[k_axis];
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let x = r.origin()[x_axis] + t * r.direction()[x_axis];
+        let y = r.origin()[y_axis] + t * r.direction()[y_axis];
+        if x < self.x0 || x > self.x1 || y < self.y0 || y > self.y1 {
+            return None;
+        }
+
+        Some((t, x, y))
+    }
 }
 
 impl Hitable for Plane {
@@ -54,23 +73,20 @@ impl Hitable for Plane {
         ))
     }
 
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<crate::HitRecord> {
+    fn hit(
+        &self,
+        r: &crate::Ray,
+        t_min: f64,
+        t_max: f64,
+        _: &mut dyn RngCore,
+    ) -> Option<crate::HitRecord> {
         let (k_axis, x_axis, y_axis) = self.get_orientation_axis();
 
         let x0 = self.x0;
         let x1 = self.x1;
         let y0 = self.y0;
         let y1 = self.y1;
-        let t = (self.k - r.origin()[k_axis]) / r.direction()[k_axis];
-        if t < t_min || t > t_max {
-            return None;
-        }
-
-        let x = r.origin()[x_axis] + t * r.direction()[x_axis];
-        let y = r.origin()[y_axis] + t * r.direction()[y_axis];
-        if x < x0 || x > x1 || y < y0 || y > y1 {
-            return None;
-        }
+        let (t, x, y) = self.intersect(r, t_min, t_max)?;
 
         let mut rec = HitRecord {
             u: (x - x0) / (x1 - x0),
@@ -80,10 +96,46 @@ impl Hitable for Plane {
             t,
             normal: Vec3::new(0.0, 0.0, 0.0),
             front_face: false,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         };
         let mut outward_normal = Vec3::new(0.0, 0.0, 0.0);
         outward_normal[k_axis] = 1.0;
         rec.set_face_normal(r, outward_normal);
+        rec.tangent[x_axis] = x1 - x0;
+        rec.bitangent[y_axis] = y1 - y0;
         Some(rec)
     }
+    fn is_light(&self) -> bool {
+        self.mat.is_emissive()
+    }
+    fn can_sample(&self) -> bool {
+        true
+    }
+    // Uniform over the area, converted to solid angle from `origin`
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let (t, _, _) =
+            match self.intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY) {
+                Some(hit) => hit,
+                None => return 0.0,
+            };
+
+        let (k_axis, _, _) = self.get_orientation_axis();
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        let distance_squared = t.powi(2) * direction.length().powi(2);
+        let cosine = (direction[k_axis] / direction.length()).abs();
+
+        distance_squared / (cosine * area)
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (k_axis, x_axis, y_axis) = self.get_orientation_axis();
+
+        let mut point = Point3::new(0.0, 0.0, 0.0);
+        point[k_axis] = self.k;
+        let (u, v) = sampler.next_2d();
+        point[x_axis] = self.x0 + u * (self.x1 - self.x0);
+        point[y_axis] = self.y0 + v * (self.y1 - self.y0);
+
+        point - origin
+    }
 }
@@ -0,0 +1,506 @@
+use std::hash::Hasher;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rayon::prelude::{IntoParallelIterator, ParallelIterator};
+
+use crate::checkpoint::Fnv1a;
+use crate::tonemap::luminance;
+use crate::{
+    AdaptiveSampling, Camera, Checkpoint, Color, Config, Film, Framebuffer, HitRecord, Hitable,
+    Lobe, Point3, Progress, ProgressStats, Ray, Sampler, Scene, Vec3, World,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Sequential,
+    Rayon,
+}
+
+// Pixel rectangle [x0, x1) x [y0, y1) with y counted from the top of the image
+#[derive(Clone, Copy, Debug)]
+pub struct Tile {
+    pub x0: u64,
+    pub y0: u64,
+    pub x1: u64,
+    pub y1: u64,
+}
+
+impl Tile {
+    pub fn width(&self) -> u64 {
+        self.x1 - self.x0
+    }
+    pub fn height(&self) -> u64 {
+        self.y1 - self.y0
+    }
+}
+
+pub struct Renderer {
+    config: Config,
+    world: World,
+    camera: Camera,
+    // Indices of the objects in `world` that are sampled directly as lights
+    lights: Vec<usize>,
+}
+
+impl Renderer {
+    pub fn new(config: Config, world: World, camera: Camera) -> Self {
+        let lights = world
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.is_light())
+            .map(|(index, _)| index)
+            .collect();
+
+        Self {
+            config,
+            world,
+            camera,
+            lights,
+        }
+    }
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn ray_color(&self, r: &Ray, depth: u64, sampler: &mut dyn Sampler) -> Color {
+        self.trace(r, depth, sampler, &mut 0)
+    }
+
+    // Follows a path of at most `depth` rays, keeping the product of the attenuations so far in
+    // `throughput`. Past `config.roulette_depth` bounces the path survives with a probability
+    // following its throughput and is weighted up when it does, which keeps the estimate
+    // unbiased while dim paths end early.
+    // Light sampling and material sampling are combined with multiple importance sampling,
+    // `scattering_pdf` is the density with which the material at the origin of `ray` picked its
+    // direction, None for camera rays and specular bounces that light sampling can't produce.
+    // Counts every traced ray in `rays` for progress reporting
+    fn trace(&self, r: &Ray, depth: u64, sampler: &mut dyn Sampler, rays: &mut u64) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = Ray::new(r.origin(), r.direction(), r.time());
+        let mut scattering_pdf = None;
+
+        for bounce in 0..depth {
+            *rays += 1;
+            let rec = match self.world.hit(&ray, 0.001, f64::INFINITY, sampler) {
+                Some(rec) => rec,
+                None => {
+                    let mut background = self.config.background.color(ray.direction());
+                    if let Some(scattering_pdf) = scattering_pdf {
+                        let light_pdf = self.light_pdf(ray.origin(), ray.direction());
+                        background = power_heuristic(scattering_pdf, light_pdf) * background;
+                    }
+                    color += throughput * background;
+                    break;
+                }
+            };
+
+            // Absorbed on the way through the inside of the object
+            throughput = throughput
+                * rec
+                    .mat
+                    .transmittance(&rec, rec.t * ray.direction().length());
+
+            let mut emitted = rec.mat.emitted(rec.u, rec.v, rec.p);
+            if let Some(scattering_pdf) = scattering_pdf {
+                let light_pdf = self.light_pdf(ray.origin(), ray.direction());
+                emitted = power_heuristic(scattering_pdf, light_pdf) * emitted;
+            }
+            color += throughput * emitted;
+
+            let srec = match rec.mat.scatter(&ray, &rec, sampler) {
+                Some(srec) => srec,
+                None => break,
+            };
+            scattering_pdf = match srec.lobe {
+                Lobe::Diffuse { pdf } => {
+                    color += throughput * self.sample_light(&ray, &rec, sampler, rays);
+                    Some(pdf)
+                }
+                Lobe::Specular => None,
+            };
+            throughput = throughput * srec.attenuation;
+            ray = srec.ray;
+
+            if bounce + 1 >= self.config.roulette_depth {
+                let survival = throughput
+                    .x()
+                    .max(throughput.y())
+                    .max(throughput.z())
+                    .min(0.95);
+                if survival <= 0.0 || sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+
+        color
+    }
+
+    // The emissive objects, and the background if it's sampled as well
+    fn light_count(&self) -> usize {
+        self.lights.len() + usize::from(self.config.background.is_light())
+    }
+
+    // Density of sampling `direction` from `origin` by picking one of the lights at random
+    fn light_pdf(&self, origin: Point3, direction: Vec3) -> f64 {
+        let count = self.light_count();
+        if count == 0 {
+            return 0.0;
+        }
+        let mut sum: f64 = self
+            .lights
+            .iter()
+            .map(|&light| self.world[light].pdf_value(origin, direction))
+            .sum();
+        if self.config.background.is_light() {
+            sum += self.config.background.pdf_value(direction);
+        }
+
+        sum / count as f64
+    }
+
+    // Light arriving at `rec` from a point sampled on one of the lights, reflected along `r`
+    fn sample_light(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+    ) -> Color {
+        let black = Color::new(0.0, 0.0, 0.0);
+        let count = self.light_count();
+        if count == 0 {
+            return black;
+        }
+
+        let light = sampler.next_index(count);
+        let direction = match self.lights.get(light) {
+            Some(&light) => self.world[light].random(rec.p, sampler),
+            None => self.config.background.random(sampler),
+        };
+        let shadow_ray = Ray::new(rec.p, direction, r.time());
+        let light_pdf = self.light_pdf(shadow_ray.origin(), shadow_ray.direction());
+
+        let wi = shadow_ray.direction().normalized();
+        let wo = (-1.0) * r.direction().normalized();
+        let scattering_pdf = rec.mat.pdf(rec, wi, wo);
+        if light_pdf <= 0.0 || scattering_pdf <= 0.0 {
+            return black;
+        }
+
+        // Whatever is hit first, so occluders and other lights are accounted for
+        *rays += 1;
+        let emitted = match self.world.hit(&shadow_ray, 0.001, f64::INFINITY, sampler) {
+            Some(light_rec) => light_rec.mat.emitted(light_rec.u, light_rec.v, light_rec.p),
+            None => self.config.background.color(wi),
+        };
+
+        let weight = power_heuristic(light_pdf, scattering_pdf);
+        (weight / light_pdf) * rec.mat.eval(rec, wi, wo) * emitted
+    }
+
+    // Splats the samples with index in `samples` onto `film`, `j` counts rows from the bottom.
+    // Earlier passes of the pixel are in `image`. The sampler restarts from the pixel and
+    // sample index, so the image only depends on `config.seed` and not on how the tiles are
+    // spread over threads or how the samples are split into passes
+    #[allow(clippy::too_many_arguments)]
+    fn render_pixel(
+        &self,
+        i: u64,
+        j: u64,
+        samples: Range<u64>,
+        image: &Film,
+        sampler: &mut dyn Sampler,
+        film: &mut Film,
+        rays: &mut u64,
+    ) {
+        let config = &self.config;
+        let y = config.image_height - 1 - j;
+        let mut luminance_sums = image.luminance_sums(i, y);
+        for sample in samples {
+            if let Some(adaptive) = &config.adaptive {
+                if converged(adaptive, luminance_sums) {
+                    break;
+                }
+            }
+            sampler.start_sample(i, j, sample);
+            let (random_u, random_v) = sampler.next_2d();
+
+            let u = ((i as f64) + random_u) / ((config.image_width - 1) as f64);
+            let v = ((j as f64) + random_v) / ((config.image_height - 1) as f64);
+
+            let r = self.camera.get_ray(u, v, sampler);
+            let color = self.trace(&r, config.max_depth, sampler, rays);
+            film.add_sample(
+                i as f64 + random_u,
+                (config.image_height - j) as f64 - random_v,
+                color,
+            );
+
+            let luminance = luminance(color);
+            film.count_sample(i, y, luminance);
+            luminance_sums.0 += 1;
+            luminance_sums.1 += luminance;
+            luminance_sums.2 += luminance * luminance;
+        }
+    }
+
+    // One pass over the pixels of `tile`, on a film of its own that also covers the border the
+    // filter reaches into
+    fn render_tile(
+        &self,
+        tile: Tile,
+        samples: Range<u64>,
+        checkpoint: &Checkpoint,
+        rays: &mut u64,
+    ) -> Film {
+        let config = &self.config;
+        let mut sampler = config
+            .sampler
+            .build(config.seed, checkpoint.sequence_length);
+        let image = &checkpoint.film;
+        let mut film = image.tile(tile);
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                let j = config.image_height - 1 - y;
+                let samples = samples.clone();
+                self.render_pixel(x, j, samples, image, sampler.as_mut(), &mut film, rays);
+            }
+        }
+
+        film
+    }
+
+    pub fn tiles(&self) -> Vec<Tile> {
+        let config = &self.config;
+        let size = config.tile_size.max(1);
+        let mut tiles = Vec::new();
+        for y0 in (0..config.image_height).step_by(size as usize) {
+            for x0 in (0..config.image_width).step_by(size as usize) {
+                tiles.push(Tile {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(config.image_width),
+                    y1: (y0 + size).min(config.image_height),
+                });
+            }
+        }
+
+        tiles
+    }
+
+    pub fn render(&self, backend: Backend) -> Framebuffer {
+        self.render_with_progress(backend, &())
+    }
+
+    pub fn render_with_progress(&self, backend: Backend, progress: &dyn Progress) -> Framebuffer {
+        let mut framebuffer = self.render_film(backend, progress).to_framebuffer();
+        framebuffer.set_tone_mapping(self.config.tone_mapping);
+
+        framebuffer
+    }
+
+    // The accumulated samples before they are resolved into an image, also holding how many
+    // samples every pixel got
+    pub fn render_film(&self, backend: Backend, progress: &dyn Progress) -> Film {
+        let mut checkpoint = Checkpoint::new(self);
+        let samples_per_pixel = self.config.samples_per_pixel;
+        self.render_passes(
+            backend,
+            progress,
+            &mut checkpoint,
+            samples_per_pixel,
+            &mut |_| {},
+        );
+
+        checkpoint.film
+    }
+
+    // Adds passes of `pass_samples` samples per pixel to `checkpoint` until it has
+    // `samples_per_pixel`, calling `pass_done` after each one, e.g. to save it
+    pub fn render_passes(
+        &self,
+        backend: Backend,
+        progress: &dyn Progress,
+        checkpoint: &mut Checkpoint,
+        pass_samples: u64,
+        pass_done: &mut dyn FnMut(&Checkpoint),
+    ) {
+        let samples_per_pixel = self.config.samples_per_pixel;
+        let pass_samples = pass_samples.max(1);
+        let passes = samples_per_pixel
+            .saturating_sub(checkpoint.samples)
+            .div_ceil(pass_samples);
+        let tiles = self.tiles();
+        let tiles_total = tiles.len() * passes as usize;
+        let tiles_done = AtomicUsize::new(0);
+        let total_rays = AtomicU64::new(0);
+        let start = Instant::now();
+
+        let stats = || ProgressStats {
+            tiles_done: tiles_done.load(Ordering::Relaxed),
+            tiles_total,
+            rays: total_rays.load(Ordering::Relaxed),
+            elapsed: start.elapsed(),
+        };
+
+        while checkpoint.samples < samples_per_pixel {
+            let samples =
+                checkpoint.samples..(checkpoint.samples + pass_samples).min(samples_per_pixel);
+            let previous = &*checkpoint;
+            let render_tile = |tile: Tile| {
+                let mut rays = 0;
+                let tile_film = self.render_tile(tile, samples.clone(), previous, &mut rays);
+
+                total_rays.fetch_add(rays, Ordering::Relaxed);
+                tiles_done.fetch_add(1, Ordering::Relaxed);
+                progress.tile_done(&stats());
+
+                tile_film
+            };
+
+            let rendered: Vec<Film> = match backend {
+                Backend::Sequential => tiles.iter().copied().map(render_tile).collect(),
+                Backend::Rayon => tiles.clone().into_par_iter().map(render_tile).collect(),
+            };
+
+            // Always in tile order, so the sums come out the same on any number of threads
+            for tile_film in &rendered {
+                checkpoint.film.merge_tile(tile_film);
+            }
+            checkpoint.samples = samples.end;
+            pass_done(checkpoint);
+        }
+        progress.finish(&stats());
+    }
+
+    // Fingerprint of the scene and the settings that change the samples, to tell whether a
+    // checkpoint belongs to this render. The sample count, adaptive sampling and tone mapping
+    // are free to change between runs
+    pub fn scene_hash(&self) -> u64 {
+        let config = &self.config;
+        let camera = &config.camera_config;
+        let mut hasher = Fnv1a::default();
+
+        for value in [
+            config.image_width,
+            config.image_height,
+            config.max_depth,
+            config.roulette_depth,
+            config.seed,
+            config.source_hash,
+            self.world.len() as u64,
+            self.lights.len() as u64,
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+        hasher.write(format!("{:?} {:?}", config.sampler, config.filter.kind).as_bytes());
+
+        let mut floats = vec![
+            config.filter.radius,
+            camera.aperture,
+            camera.vfov,
+            camera.dist_to_focus,
+            camera.time0,
+            camera.time1,
+        ];
+        let mut vectors = vec![camera.lookfrom, camera.lookat, camera.vup];
+        if let Some(bbox) = self.world.bounding_box(camera.time0, camera.time1) {
+            vectors.extend([bbox.min(), bbox.max()]);
+        }
+        // The background seen along the axes and the diagonals
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    if (x, y, z) != (0, 0, 0) {
+                        let direction = Vec3::new(x as f64, y as f64, z as f64);
+                        vectors.push(config.background.color(direction));
+                    }
+                }
+            }
+        }
+        for v in vectors {
+            floats.extend([v.x(), v.y(), v.z()]);
+        }
+        for value in floats {
+            hasher.write(&value.to_bits().to_le_bytes());
+        }
+
+        hasher.finish()
+    }
+}
+
+// Whether the standard error of the mean luminance of a pixel is small enough to stop, from
+// its sample count and the sum of the luminance and its square
+fn converged(adaptive: &AdaptiveSampling, (count, sum, sum_squared): (u64, f64, f64)) -> bool {
+    if count < adaptive.min_samples.max(2) {
+        return false;
+    }
+    let n = count as f64;
+    let mean = sum / n;
+    let variance = ((sum_squared - sum * mean) / (n - 1.0)).max(0.0);
+
+    (variance / n).sqrt() <= adaptive.threshold * mean.max(MIN_LUMINANCE)
+}
+
+// Floor of the mean luminance the adaptive threshold is relative to, so nearly black pixels
+// don't need an error of 0 to stop
+const MIN_LUMINANCE: f64 = 0.01;
+
+// Weight of a sample taken with density `pdf` when `other_pdf` could have produced it too
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let pdf = pdf * pdf;
+    pdf / (pdf + other_pdf * other_pdf)
+}
+
+impl From<Scene> for Renderer {
+    fn from((config, world, camera): Scene) -> Self {
+        Self::new(config, world, camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cornell_box_scene_with_smoke_boxes;
+
+    fn small_cornell_box(seed: u64) -> Renderer {
+        let (mut config, world, camera) = cornell_box_scene_with_smoke_boxes();
+        config.image_width = 24;
+        config.image_height = 24;
+        config.samples_per_pixel = 8;
+        config.tile_size = 8;
+        config.seed = seed;
+        Renderer::new(config, world, camera)
+    }
+
+    fn pixels(framebuffer: &Framebuffer) -> Vec<[f64; 3]> {
+        let pixels = framebuffer.pixels().iter();
+        pixels.map(|c| [c.x(), c.y(), c.z()]).collect()
+    }
+
+    #[test]
+    fn same_seed_renders_the_same_image() {
+        let renderer = small_cornell_box(3);
+        let sequential = pixels(&renderer.render(Backend::Sequential));
+        assert_eq!(pixels(&renderer.render(Backend::Rayon)), sequential);
+        assert_eq!(
+            pixels(&small_cornell_box(3).render(Backend::Rayon)),
+            sequential
+        );
+        assert_ne!(
+            pixels(&small_cornell_box(4).render(Backend::Rayon)),
+            sequential
+        );
+    }
+}
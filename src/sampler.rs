@@ -0,0 +1,486 @@
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use rand::{Rng, RngCore};
+
+use crate::pcg::mix;
+use crate::Pcg32;
+
+// Largest f64 below 1
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// Random numbers of one pixel sample. Every `next_1d` / `next_2d` moves on to the next dimension,
+// so the camera, lights and materials should always ask for them in the same order to get
+// the benefit of the stratification. The `RngCore` part is for decisions not worth a dimension
+pub trait Sampler: RngCore {
+    // Restarts the dimensions for sample `index` of pixel (x, y)
+    fn start_sample(&mut self, x: u64, y: u64, index: u64);
+    fn next_1d(&mut self) -> f64;
+    fn next_2d(&mut self) -> (f64, f64);
+    // One of 0..len from a single dimension
+    fn next_index(&mut self, len: usize) -> usize {
+        ((self.next_1d() * len as f64) as usize).min(len.saturating_sub(1))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SamplerKind {
+    // Independent uniform numbers
+    Random,
+    // Jittered grid for every dimension, shuffled so dimensions don't correlate
+    Stratified,
+    // Halton sequence with the digits scrambled per pixel
+    Halton,
+    // Owen scrambled Sobol points, shuffled per pixel and dimension pair
+    #[default]
+    Sobol,
+    // Sobol points rotated per pixel by a blue noise mask, so the remaining noise has no
+    // low frequencies
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub fn build(self, seed: u64, samples_per_pixel: u64) -> Box<dyn Sampler> {
+        let state = SampleState::new(seed, samples_per_pixel);
+        match self {
+            SamplerKind::Random => Box::new(RandomSampler { state }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { state }),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler { state }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(SamplerKind::Random),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            "blue_noise" => Ok(SamplerKind::BlueNoise),
+            other => Err(format!(
+                "unknown sampler `{}`, expected random, stratified, halton, sobol or blue_noise",
+                other
+            )),
+        }
+    }
+}
+
+// What every sampler tracks about the current sample
+struct SampleState {
+    seed: u64,
+    samples_per_pixel: u64,
+    x: u64,
+    y: u64,
+    index: u64,
+    dimension: u64,
+    rng: Pcg32,
+}
+
+impl SampleState {
+    fn new(seed: u64, samples_per_pixel: u64) -> Self {
+        Self {
+            seed,
+            samples_per_pixel: samples_per_pixel.max(1),
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+            rng: Pcg32::new(seed, 0),
+        }
+    }
+    fn start(&mut self, x: u64, y: u64, index: u64) {
+        self.x = x;
+        self.y = y;
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Pcg32::for_sample(self.seed, hash(&[x, y]), index);
+    }
+    // Index of the dimension handed out next, `count` dimensions are taken
+    fn take(&mut self, count: u64) -> u64 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+    // Same for all samples of a pixel, different for every dimension
+    fn pixel_hash(&self, dimension: u64) -> u64 {
+        hash(&[self.seed, self.x, self.y, dimension])
+    }
+    // `index` shuffled within its block of `samples_per_pixel`
+    fn permuted_index(&self, hash: u64) -> u64 {
+        let n = self.samples_per_pixel;
+        let block = self.index / n;
+        let permuted = permutation_element(
+            (self.index % n) as u32,
+            n as u32,
+            (hash ^ mix(block)) as u32,
+        );
+
+        block * n + permuted as u64
+    }
+}
+
+// Forwards `RngCore` to the generator of the current sample
+macro_rules! sample_rng {
+    ($sampler:ty) => {
+        impl RngCore for $sampler {
+            fn next_u32(&mut self) -> u32 {
+                self.state.rng.next_u32()
+            }
+            fn next_u64(&mut self) -> u64 {
+                self.state.rng.next_u64()
+            }
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                self.state.rng.fill_bytes(dest)
+            }
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+                self.state.rng.try_fill_bytes(dest)
+            }
+        }
+    };
+}
+
+pub struct RandomSampler {
+    state: SampleState,
+}
+
+sample_rng!(RandomSampler);
+
+impl Sampler for RandomSampler {
+    fn start_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.state.start(x, y, index);
+    }
+    fn next_1d(&mut self) -> f64 {
+        self.state.rng.gen()
+    }
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.state.rng.gen(), self.state.rng.gen())
+    }
+}
+
+pub struct StratifiedSampler {
+    state: SampleState,
+}
+
+sample_rng!(StratifiedSampler);
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.state.start(x, y, index);
+    }
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take(1);
+        let n = self.state.samples_per_pixel;
+        let stratum = self.shuffled_stratum(n, dimension);
+        let jitter: f64 = self.state.rng.gen();
+
+        ((stratum as f64 + jitter) / n as f64).min(ONE_MINUS_EPSILON)
+    }
+    // The grid has at least `samples_per_pixel` cells, with some left empty when that isn't a
+    // product of close numbers
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.take(2);
+        let nx = (self.state.samples_per_pixel as f64).sqrt().ceil() as u64;
+        let ny = self.state.samples_per_pixel.div_ceil(nx);
+        let stratum = self.shuffled_stratum(nx * ny, dimension);
+        let (jx, jy): (f64, f64) = (self.state.rng.gen(), self.state.rng.gen());
+
+        (
+            (((stratum % nx) as f64 + jx) / nx as f64).min(ONE_MINUS_EPSILON),
+            (((stratum / nx) as f64 + jy) / ny as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+impl StratifiedSampler {
+    // Every sample of the pixel lands in a different one of `strata` cells as long as there are
+    // no more samples than cells
+    fn shuffled_stratum(&self, strata: u64, dimension: u64) -> u64 {
+        let p = self.state.pixel_hash(dimension) ^ mix(self.state.index / strata);
+        permutation_element((self.state.index % strata) as u32, strata as u32, p as u32) as u64
+    }
+}
+
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+sample_rng!(HaltonSampler);
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.state.start(x, y, index);
+    }
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take(1);
+        self.halton(dimension)
+    }
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.take(2);
+        (self.halton(dimension), self.halton(dimension + 1))
+    }
+}
+
+impl HaltonSampler {
+    // Plain random numbers past the primes
+    fn halton(&mut self, dimension: u64) -> f64 {
+        let Some(&base) = PRIMES.get(dimension as usize) else {
+            return self.state.rng.gen();
+        };
+
+        scrambled_radical_inverse(base, self.state.index, self.state.pixel_hash(dimension))
+    }
+}
+
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+sample_rng!(SobolSampler);
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.state.start(x, y, index);
+    }
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take(1);
+        let hash = self.state.pixel_hash(dimension);
+        let index = self.state.permuted_index(hash);
+
+        to_unit_u32(owen_scramble(sobol_0(index), (hash >> 32) as u32))
+    }
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.take(2);
+        let hash = self.state.pixel_hash(dimension);
+        let index = self.state.permuted_index(hash);
+
+        (
+            to_unit_u32(owen_scramble(sobol_0(index), (hash >> 32) as u32)),
+            to_unit_u32(owen_scramble(sobol_1(index), mix(hash) as u32)),
+        )
+    }
+}
+
+pub struct BlueNoiseSampler {
+    state: SampleState,
+}
+
+sample_rng!(BlueNoiseSampler);
+
+impl Sampler for BlueNoiseSampler {
+    fn start_sample(&mut self, x: u64, y: u64, index: u64) {
+        self.state.start(x, y, index);
+    }
+    fn next_1d(&mut self) -> f64 {
+        let dimension = self.state.take(1);
+        let hash = hash(&[self.state.seed, dimension]);
+        let index = self.state.permuted_index(hash);
+        let value = to_unit_u32(owen_scramble(sobol_0(index), (hash >> 32) as u32));
+
+        self.rotate(value, dimension)
+    }
+    fn next_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.take(2);
+        let hash = hash(&[self.state.seed, dimension]);
+        let index = self.state.permuted_index(hash);
+        let u = to_unit_u32(owen_scramble(sobol_0(index), (hash >> 32) as u32));
+        let v = to_unit_u32(owen_scramble(sobol_1(index), mix(hash) as u32));
+
+        (self.rotate(u, dimension), self.rotate(v, dimension + 1))
+    }
+}
+
+impl BlueNoiseSampler {
+    // Every pixel shares the same points, shifted by the mask value of the pixel. Each dimension
+    // reads the mask at a different offset so the shifts don't repeat between dimensions
+    fn rotate(&self, value: f64, dimension: u64) -> f64 {
+        let offset = hash(&[self.state.seed, dimension, 1]);
+        let x = self.state.x.wrapping_add(offset) % BLUE_NOISE_SIZE as u64;
+        let y = self.state.y.wrapping_add(offset >> 32) % BLUE_NOISE_SIZE as u64;
+        let value = value + blue_noise()[y as usize * BLUE_NOISE_SIZE + x as usize];
+
+        (value - value.floor()).min(ONE_MINUS_EPSILON)
+    }
+}
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+// Tileable mask of values in [0, 1) where pixels with close values are far apart, made once
+// with the void and cluster method: every pixel is ranked by how empty its surroundings are
+// when it gets picked
+fn blue_noise() -> &'static [f64] {
+    static MASK: OnceLock<Vec<f64>> = OnceLock::new();
+    MASK.get_or_init(|| {
+        const SIGMA: f64 = 1.5;
+        let size = BLUE_NOISE_SIZE;
+        let n = size * size;
+        let wrap = |d: usize| d.min(size - d) as f64;
+        let kernel: Vec<f64> = (0..n)
+            .map(|k| {
+                let (dx, dy) = (wrap(k % size), wrap(k / size));
+                (-(dx * dx + dy * dy) / (2.0 * SIGMA * SIGMA)).exp()
+            })
+            .collect();
+
+        // A tiny random energy to break the ties of the empty mask
+        let mut rng = Pcg32::new(0, 0);
+        let mut energy: Vec<f64> = (0..n).map(|_| 1.0e-9 * rng.gen::<f64>()).collect();
+        let mut rank = vec![None; n];
+        for r in 0..n {
+            let void = (0..n)
+                .filter(|&p| rank[p].is_none())
+                .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+                .unwrap();
+            rank[void] = Some(r);
+
+            let (vx, vy) = (void % size, void / size);
+            for (p, e) in energy.iter_mut().enumerate() {
+                let dx = (p % size + size - vx) % size;
+                let dy = (p / size + size - vy) % size;
+                *e += kernel[dy * size + dx];
+            }
+        }
+
+        rank.into_iter()
+            .map(|r| (r.unwrap() as f64 + 0.5) / n as f64)
+            .collect()
+    })
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// `index` written in `base` and mirrored around the point, with every digit shuffled by a
+// permutation that depends on the digits before it (Owen scrambling). Without it the first
+// points of the large bases all bunch up near 0
+fn scrambled_radical_inverse(base: u64, mut index: u64, hash: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0;
+    // Base to the number of digits taken, `reversed` is below it
+    let mut base_n: u64 = 1;
+    // Until the digits are below the precision of the result, or would no longer fit in a u64
+    while 1.0 - (base - 1) as f64 * inv_base_n < 1.0 {
+        let Some(next_base_n) = base_n.checked_mul(base) else {
+            break;
+        };
+        base_n = next_base_n;
+        let digit = index % base;
+        let digit_hash = mix(hash ^ reversed) as u32;
+        reversed =
+            reversed * base + permutation_element(digit as u32, base as u32, digit_hash) as u64;
+        inv_base_n *= inv_base;
+        index /= base;
+    }
+
+    (reversed as f64 * inv_base_n).min(ONE_MINUS_EPSILON)
+}
+
+// First two dimensions of the Sobol sequence, together a (0, 2)-sequence
+fn sobol_0(index: u64) -> u32 {
+    (index as u32).reverse_bits()
+}
+fn sobol_1(index: u64) -> u32 {
+    let mut v = 1 << 31;
+    let mut result = 0;
+    let mut i = index as u32;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+
+    result
+}
+
+// Hash based Owen scrambling (Burley 2020), keeps the stratification of the points
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x05526c56);
+    v ^= v.wrapping_mul(0x53a22864);
+    v.reverse_bits()
+}
+
+// Element `i` of a random permutation of 0..l picked by `p`, without storing it (Kensler 2013)
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+
+    (i.wrapping_add(p)) % l
+}
+
+fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |h, &v| mix(h ^ v))
+}
+
+fn to_unit_u32(bits: u32) -> f64 {
+    (bits as f64 / (1u64 << 32) as f64).min(ONE_MINUS_EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_dimensions_are_uniform() {
+        const SAMPLES: u64 = 4096;
+        let dimensions = PRIMES.len();
+        let mut sampler = SamplerKind::Halton.build(7, SAMPLES);
+        let mut sums = vec![0.0; dimensions];
+        for index in 0..SAMPLES {
+            sampler.start_sample(3, 5, index);
+            for sum in sums.iter_mut() {
+                let value = sampler.next_1d();
+                assert!((0.0..1.0).contains(&value), "{} out of [0, 1)", value);
+                *sum += value;
+            }
+        }
+
+        for (dimension, sum) in sums.iter().enumerate() {
+            let mean = sum / SAMPLES as f64;
+            assert!(
+                (mean - 0.5).abs() < 0.01,
+                "dimension {} has mean {}",
+                dimension,
+                mean
+            );
+        }
+    }
+}
@@ -0,0 +1,1126 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::checkpoint::Fnv1a;
+use crate::obj::load_obj_with_sources;
+use crate::{
+    AdaptiveSampling, Background, BumpMap, Camera, CameraConfig, CameraConfigOptions,
+    CheckerTexture, Coated, Conductor, Config, ConfigOptions, ConstantBackground, ConstantMedium,
+    Cube, Dielectric, DiffuseLight, EnvironmentMap, Filter, FilterKind, Hitable, ImageTexture,
+    Instance, Isotropic, Lambertian, Material, Matrix4, Metal, MixMaterial, MovingSphere,
+    NoiseTexture, NormalMap, Plane, PlaneOrientation, Principled, RoughDielectric, SamplerKind,
+    Scene, SkyGradient, SolidColor, Sphere, Texture, ThinDielectric, ToneMapper, Transform,
+    Triangle, Vec3, World,
+};
+
+// Scene description files are TOML documents, see scenes/cornell_box.toml for an example:
+//
+//   [config]                 image settings, the fields of `ConfigOptions`
+//   [camera]                 the fields of `CameraConfigOptions`
+//   [materials.<name>]       named materials shared between objects
+//   [geometry.<name>]        shapes built once and placed by `instance` objects
+//   [[objects]]              primitives referring to a material by name
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    config: ConfigDesc,
+    camera: CameraDesc,
+    #[serde(default)]
+    materials: BTreeMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    geometry: BTreeMap<String, Spanned<ObjectDesc>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigDesc {
+    aspect_ratio: Spanned<f64>,
+    image_width: Spanned<u64>,
+    samples_per_pixel: Spanned<u64>,
+    max_depth: u64,
+    roulette_depth: Option<u64>,
+    background_color: Option<[f64; 3]>,
+    background: Option<Spanned<BackgroundDesc>>,
+    tile_size: Option<Spanned<u64>>,
+    seed: Option<u64>,
+    sampler: Option<SamplerDesc>,
+    filter: Option<FilterDesc>,
+    filter_radius: Option<Spanned<f64>>,
+    tone_mapper: Option<ToneMapperDesc>,
+    // Stops
+    exposure: Option<f64>,
+    // Turns on adaptive sampling
+    adaptive_threshold: Option<Spanned<f64>>,
+    min_samples: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    vup: [f64; 3],
+    aperture: f64,
+    vfov: f64,
+    dist_to_focus: Option<f64>,
+    time0: Option<f64>,
+    time1: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        odd: Box<TextureDesc>,
+        even: Box<TextureDesc>,
+    },
+    Noise {
+        scale: f64,
+    },
+    // Relative paths are resolved from the directory of the scene file
+    Image {
+        file: String,
+    },
+}
+
+// Materials taking a texture accept either a plain `color` or a `texture` table
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        color: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+    },
+    Metal {
+        color: [f64; 3],
+        #[serde(default)]
+        fuzz: f64,
+    },
+    // Frosted with a `roughness`, colored with a `tint` left after `tint_distance` (default 1)
+    Dielectric {
+        ir: f64,
+        roughness: Option<ScalarDesc>,
+        tint: Option<[f64; 3]>,
+        tint_distance: Option<f64>,
+    },
+    // Window pane, `tint` is left after going straight through
+    ThinDielectric {
+        ir: f64,
+        tint: Option<[f64; 3]>,
+    },
+    // GGX metal, either a `preset` or the complex index of refraction `eta` + i `k` per channel.
+    // `roughness` is a number in [0, 1] or a texture
+    Conductor {
+        preset: Option<ConductorPresetDesc>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        roughness: Option<ScalarDesc>,
+    },
+    // Each parameter but `ir` is a number in [0, 1] or a texture, see `Principled::new` for
+    // the defaults
+    Principled {
+        color: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+        metallic: Option<ScalarDesc>,
+        roughness: Option<ScalarDesc>,
+        specular: Option<ScalarDesc>,
+        sheen: Option<ScalarDesc>,
+        clearcoat: Option<ScalarDesc>,
+        clearcoat_roughness: Option<ScalarDesc>,
+        transmission: Option<ScalarDesc>,
+        subsurface: Option<ScalarDesc>,
+        ir: Option<f64>,
+    },
+    DiffuseLight {
+        color: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+    },
+    Isotropic {
+        color: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+    },
+    // Blend of two other named materials, `weight` is the fraction of `second`
+    Mix {
+        first: String,
+        second: String,
+        weight: ScalarDesc,
+    },
+    // Another named material under a clear coat, smooth with an index of 1.5 by default
+    Coated {
+        base: String,
+        ir: Option<f64>,
+        roughness: Option<ScalarDesc>,
+        tint: Option<[f64; 3]>,
+    },
+    // Another named material with its shading normal read from a tangent space map
+    NormalMap {
+        material: String,
+        texture: TextureDesc,
+        strength: Option<f64>,
+    },
+    // Another named material on a surface raised by `height` times `scale`
+    BumpMap {
+        material: String,
+        height: TextureDesc,
+        scale: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConductorPresetDesc {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScalarDesc {
+    Value(f64),
+    Texture(Box<TextureDesc>),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Constant {
+        color: [f64; 3],
+    },
+    // Defaults to the sky of the book
+    Sky {
+        bottom: Option<[f64; 3]>,
+        top: Option<[f64; 3]>,
+    },
+    // Equirectangular .hdr or .exr image, resolved like image textures
+    Environment {
+        file: String,
+        intensity: Option<f64>,
+        // Degrees around the y axis
+        rotation: Option<f64>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SamplerDesc {
+    Random,
+    Stratified,
+    Halton,
+    Sobol,
+    BlueNoise,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FilterDesc {
+    Box,
+    Tent,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ToneMapperDesc {
+    Clamp,
+    Reinhard,
+    Aces,
+    Agx,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OrientationDesc {
+    XY,
+    YZ,
+    ZX,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TransformDesc {
+    // Degrees around the y axis
+    RotateY(f64),
+    Translate([f64; 3]),
+    // Degrees around an arbitrary axis
+    Rotate { axis: [f64; 3], angle: f64 },
+    // Per axis factors
+    Scale([f64; 3]),
+}
+
+// Unknown fields are rejected by `ShapeDesc`, serde doesn't support that on a struct with
+// flattened fields
+#[derive(Deserialize)]
+struct ObjectDesc {
+    #[serde(flatten)]
+    shape: ShapeDesc,
+    // Optional for the boundary of a constant medium
+    material: Option<String>,
+    // Applied in order
+    #[serde(default)]
+    transforms: Vec<TransformDesc>,
+}
+
+impl MaterialDesc {
+    // Names of the materials this one is built on
+    fn references(&self) -> Vec<&str> {
+        match self {
+            MaterialDesc::Mix { first, second, .. } => vec![first.as_str(), second.as_str()],
+            MaterialDesc::Coated { base, .. } => vec![base.as_str()],
+            MaterialDesc::NormalMap { material, .. } | MaterialDesc::BumpMap { material, .. } => {
+                vec![material.as_str()]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl ObjectDesc {
+    // Names of the geometry this object instances
+    fn references(&self) -> Vec<&str> {
+        match &self.shape {
+            ShapeDesc::Instance { geometry } => vec![geometry.as_str()],
+            ShapeDesc::ConstantMedium { boundary, .. } => boundary.references(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+    },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        radius: f64,
+        time0: f64,
+        time1: f64,
+    },
+    Plane {
+        orientation: OrientationDesc,
+        x0: f64,
+        x1: f64,
+        y0: f64,
+        y1: f64,
+        k: f64,
+    },
+    Cube {
+        min: [f64; 3],
+        max: [f64; 3],
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+    },
+    // The material is used for faces without an MTL material and defaults to grey
+    Obj {
+        file: String,
+    },
+    ConstantMedium {
+        boundary: Box<ObjectDesc>,
+        density: f64,
+        color: Option<[f64; 3]>,
+        texture: Option<TextureDesc>,
+    },
+    // The transforms place the geometry and the material, if any, replaces its own
+    Instance {
+        geometry: String,
+    },
+}
+
+#[derive(Debug)]
+pub struct SceneFileError {
+    pub path: Option<PathBuf>,
+    // 1-based, None when the error isn't tied to a place in the file
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "{}:", line)?;
+        }
+        if self.path.is_some() || self.line.is_some() {
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+pub fn load_scene_file(path: impl AsRef<Path>) -> Result<Scene, SceneFileError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|err| SceneFileError {
+        path: Some(path.to_path_buf()),
+        line: None,
+        message: err.to_string(),
+    })?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    parse_scene(&source, base_dir).map_err(|err| SceneFileError {
+        path: Some(path.to_path_buf()),
+        ..err
+    })
+}
+
+// `base_dir` is where relative image texture paths are looked up
+pub fn parse_scene(source: &str, base_dir: &Path) -> Result<Scene, SceneFileError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|err| SceneFileError {
+        path: None,
+        line: err.span().map(|span| line_of(source, span.start)),
+        message: err.message().to_string(),
+    })?;
+    let mut builder = Builder {
+        base_dir,
+        sources: RefCell::new(Vec::new()),
+        geometry: BTreeMap::new(),
+    };
+
+    let camera = &desc.camera;
+    let camera_config = CameraConfig::new(CameraConfigOptions {
+        lookfrom: vec3(camera.lookfrom),
+        lookat: vec3(camera.lookat),
+        vup: vec3(camera.vup),
+        aperture: camera.aperture,
+        vfov: camera.vfov,
+        dist_to_focus: camera.dist_to_focus,
+        time0: camera.time0,
+        time1: camera.time1,
+    });
+
+    positive(source, &desc.config.aspect_ratio, "aspect_ratio")?;
+    positive(source, &desc.config.image_width, "image_width")?;
+    positive(source, &desc.config.samples_per_pixel, "samples_per_pixel")?;
+
+    let mut config = Config::new(ConfigOptions {
+        aspect_ratio: *desc.config.aspect_ratio.get_ref(),
+        image_width: *desc.config.image_width.get_ref(),
+        samples_per_pixel: *desc.config.samples_per_pixel.get_ref(),
+        max_depth: desc.config.max_depth,
+        camera_config,
+        background_color: desc.config.background_color.map(vec3),
+    });
+    config.check_size().map_err(|message| {
+        error_at(
+            line_of(source, desc.config.image_width.span().start),
+            message,
+        )
+    })?;
+    if let Some(background) = &desc.config.background {
+        let line = line_of(source, background.span().start);
+        if desc.config.background_color.is_some() {
+            return Err(error_at(
+                line,
+                String::from("expected either `background_color` or `background`, found both"),
+            ));
+        }
+        config.background = builder
+            .background(background.get_ref())
+            .map_err(|message| error_at(line, message))?;
+    }
+    if let Some(roulette_depth) = desc.config.roulette_depth {
+        config.roulette_depth = roulette_depth;
+    }
+    if let Some(tile_size) = &desc.config.tile_size {
+        positive(source, tile_size, "tile_size")?;
+        config.tile_size = *tile_size.get_ref();
+    }
+    if let Some(seed) = desc.config.seed {
+        config.seed = seed;
+    }
+    if let Some(sampler) = &desc.config.sampler {
+        config.sampler = match sampler {
+            SamplerDesc::Random => SamplerKind::Random,
+            SamplerDesc::Stratified => SamplerKind::Stratified,
+            SamplerDesc::Halton => SamplerKind::Halton,
+            SamplerDesc::Sobol => SamplerKind::Sobol,
+            SamplerDesc::BlueNoise => SamplerKind::BlueNoise,
+        };
+    }
+    if let Some(filter) = &desc.config.filter {
+        config.filter = Filter::new(match filter {
+            FilterDesc::Box => FilterKind::Box,
+            FilterDesc::Tent => FilterKind::Tent,
+            FilterDesc::Gaussian => FilterKind::Gaussian,
+            FilterDesc::Mitchell => FilterKind::Mitchell,
+            FilterDesc::Lanczos => FilterKind::Lanczos,
+        });
+    }
+    if let Some(radius) = &desc.config.filter_radius {
+        positive(source, radius, "filter_radius")?;
+        config.filter.radius = *radius.get_ref();
+    }
+    if let Some(tone_mapper) = &desc.config.tone_mapper {
+        config.tone_mapping.operator = match tone_mapper {
+            ToneMapperDesc::Clamp => ToneMapper::Clamp,
+            ToneMapperDesc::Reinhard => ToneMapper::Reinhard,
+            ToneMapperDesc::Aces => ToneMapper::Aces,
+            ToneMapperDesc::Agx => ToneMapper::Agx,
+        };
+    }
+    if let Some(exposure) = desc.config.exposure {
+        config.tone_mapping.exposure = exposure;
+    }
+    if let Some(threshold) = &desc.config.adaptive_threshold {
+        positive(source, threshold, "adaptive_threshold")?;
+        let mut adaptive = AdaptiveSampling::new(*threshold.get_ref());
+        if let Some(min_samples) = desc.config.min_samples {
+            adaptive.min_samples = min_samples;
+        }
+        config.adaptive = Some(adaptive);
+    }
+
+    let mut materials = BTreeMap::new();
+    let order = build_order(source, "material", &desc.materials, |desc| {
+        desc.references()
+    })?;
+    for name in order {
+        let material = &desc.materials[name];
+        let line = line_of(source, material.span().start);
+        let material = builder
+            .material(material.get_ref(), &materials)
+            .map_err(|message| error_at(line, message))?;
+        materials.insert(name, material);
+    }
+
+    // Geometry without a material of its own is grey unless an instance overrides it
+    let grey: Arc<dyn Material> = Arc::new(Lambertian::new(SolidColor::from_rbg(0.8, 0.8, 0.8)));
+    let order = build_order(source, "geometry", &desc.geometry, |desc| desc.references())?;
+    for name in order {
+        let geometry = &desc.geometry[name];
+        let line = line_of(source, geometry.span().start);
+        let geometry = builder
+            .object(geometry.get_ref(), &materials, Some(grey.clone()))
+            .map_err(|message| error_at(line, message))?;
+        builder
+            .geometry
+            .insert(name.to_string(), Arc::from(geometry));
+    }
+
+    let mut world = World::new();
+    for object in &desc.objects {
+        let line = line_of(source, object.span().start);
+        let object = builder
+            .object(object.get_ref(), &materials, None)
+            .map_err(|message| error_at(line, message))?;
+        world.push(object);
+    }
+
+    config.source_hash = builder
+        .source_hash(source)
+        .map_err(|message| SceneFileError {
+            path: None,
+            line: None,
+            message,
+        })?;
+
+    let camera = Camera::new(&config.camera_config, config.aspect_ratio);
+
+    Ok((config, world, camera))
+}
+
+struct Builder<'a> {
+    base_dir: &'a Path,
+    // Every file read while building, hashed with the scene file for checkpoints
+    sources: RefCell<Vec<PathBuf>>,
+    // Each built before the geometry instancing it
+    geometry: BTreeMap<String, Arc<dyn Hitable>>,
+}
+
+impl<'a> Builder<'a> {
+    // Path of a file named in the scene, recorded as one of its sources
+    fn source(&self, file: &str) -> PathBuf {
+        let path = self.base_dir.join(file);
+        self.sources.borrow_mut().push(path.clone());
+        path
+    }
+
+    // The scene file and the bytes of every file it read
+    fn source_hash(&self, source: &str) -> Result<u64, String> {
+        let mut hasher = Fnv1a::default();
+        hasher.write(source.as_bytes());
+        for path in self.sources.borrow().iter() {
+            let bytes = std::fs::read(path)
+                .map_err(|err| format!("could not read `{}`: {}", path.display(), err))?;
+            hasher.write(&(bytes.len() as u64).to_le_bytes());
+            hasher.write(&bytes);
+        }
+
+        Ok(hasher.finish())
+    }
+
+    fn texture(&self, desc: &TextureDesc) -> Result<Box<dyn Texture>, String> {
+        self.texture_of(desc, true)
+    }
+    // Roughness, weights, heights and normals, with images read as is
+    fn data_texture(&self, desc: &TextureDesc) -> Result<Box<dyn Texture>, String> {
+        self.texture_of(desc, false)
+    }
+    // `srgb` decodes the colors of images
+    fn texture_of(&self, desc: &TextureDesc, srgb: bool) -> Result<Box<dyn Texture>, String> {
+        Ok(match desc {
+            TextureDesc::Solid { color } => Box::new(SolidColor::new(vec3(*color))),
+            TextureDesc::Checker { odd, even } => Box::new(CheckerTexture::new(
+                self.texture_of(odd, srgb)?,
+                self.texture_of(even, srgb)?,
+            )),
+            TextureDesc::Noise { scale } => Box::new(NoiseTexture::new(*scale)),
+            TextureDesc::Image { file } => {
+                let path = self.source(file);
+                let texture = ImageTexture::open(&path)
+                    .map_err(|err| format!("could not load image `{}`: {}", path.display(), err))?;
+                Box::new(texture.with_srgb(srgb))
+            }
+        })
+    }
+
+    fn background(&self, desc: &BackgroundDesc) -> Result<Box<dyn Background>, String> {
+        Ok(match desc {
+            BackgroundDesc::Constant { color } => Box::new(ConstantBackground::new(vec3(*color))),
+            BackgroundDesc::Sky { bottom, top } => {
+                let default = SkyGradient::default();
+                Box::new(SkyGradient::new(
+                    bottom.map(vec3).unwrap_or(default.bottom()),
+                    top.map(vec3).unwrap_or(default.top()),
+                ))
+            }
+            BackgroundDesc::Environment {
+                file,
+                intensity,
+                rotation,
+            } => {
+                let path = self.source(file);
+                let map = EnvironmentMap::open(&path).map_err(|err| {
+                    format!(
+                        "could not load environment map `{}`: {}",
+                        path.display(),
+                        err
+                    )
+                })?;
+                Box::new(
+                    map.with_intensity(intensity.unwrap_or(1.0))
+                        .with_rotation(rotation.unwrap_or(0.0)),
+                )
+            }
+        })
+    }
+
+    fn scalar(&self, desc: &ScalarDesc) -> Result<Box<dyn Texture>, String> {
+        match desc {
+            ScalarDesc::Value(value) => Ok(Box::new(SolidColor::from_rbg(*value, *value, *value))),
+            ScalarDesc::Texture(texture) => self.data_texture(texture),
+        }
+    }
+
+    // Smooth when missing
+    fn roughness(&self, desc: &Option<ScalarDesc>) -> Result<Box<dyn Texture>, String> {
+        match desc {
+            Some(desc) => self.scalar(desc),
+            None => Ok(Box::new(SolidColor::from_rbg(0.0, 0.0, 0.0))),
+        }
+    }
+
+    fn color_or_texture(
+        &self,
+        color: &Option<[f64; 3]>,
+        texture: &Option<TextureDesc>,
+    ) -> Result<Box<dyn Texture>, String> {
+        match (color, texture) {
+            (Some(color), None) => Ok(Box::new(SolidColor::new(vec3(*color)))),
+            (None, Some(texture)) => self.texture(texture),
+            (Some(_), Some(_)) => Err(String::from(
+                "expected either `color` or `texture`, found both",
+            )),
+            (None, None) => Err(String::from("missing `color` or `texture`")),
+        }
+    }
+
+    // `materials` holds the ones `desc` refers to, built first like the geometry
+    fn material(
+        &self,
+        desc: &MaterialDesc,
+        materials: &BTreeMap<&str, Arc<dyn Material>>,
+    ) -> Result<Arc<dyn Material>, String> {
+        let named = |name: &String| {
+            materials
+                .get(name.as_str())
+                .cloned()
+                .ok_or_else(|| format!("unknown material `{}`", name))
+        };
+        Ok(match desc {
+            MaterialDesc::Lambertian { color, texture } => {
+                Arc::new(Lambertian::new(self.color_or_texture(color, texture)?))
+            }
+            MaterialDesc::Metal { color, fuzz } => Arc::new(Metal::new(vec3(*color), *fuzz)),
+            MaterialDesc::Dielectric {
+                ir,
+                roughness,
+                tint,
+                tint_distance,
+            } => {
+                let tint = vec3(tint.unwrap_or([1.0, 1.0, 1.0]));
+                let distance = tint_distance.unwrap_or(1.0);
+                if distance <= 0.0 {
+                    return Err(String::from("`tint_distance` must be positive"));
+                }
+                match roughness {
+                    Some(_) => Arc::new(
+                        RoughDielectric::new(*ir, self.roughness(roughness)?)
+                            .with_tint(tint, distance),
+                    ),
+                    None => Arc::new(Dielectric::new(*ir).with_tint(tint, distance)),
+                }
+            }
+            MaterialDesc::ThinDielectric { ir, tint } => {
+                Arc::new(ThinDielectric::new(*ir).with_tint(vec3(tint.unwrap_or([1.0, 1.0, 1.0]))))
+            }
+            MaterialDesc::Conductor {
+                preset,
+                eta,
+                k,
+                roughness,
+            } => {
+                let (eta, k) = match (preset, eta, k) {
+                    (Some(preset), None, None) => {
+                        let metal = match preset {
+                            ConductorPresetDesc::Gold => Conductor::gold(0.0),
+                            ConductorPresetDesc::Copper => Conductor::copper(0.0),
+                            ConductorPresetDesc::Aluminium => Conductor::aluminium(0.0),
+                        };
+                        (metal.eta(), metal.k())
+                    }
+                    (None, Some(eta), Some(k)) => (vec3(*eta), vec3(*k)),
+                    _ => {
+                        return Err(String::from(
+                            "expected either `preset` or both `eta` and `k`",
+                        ))
+                    }
+                };
+                Arc::new(Conductor::new(eta, k, self.roughness(roughness)?))
+            }
+            MaterialDesc::Principled {
+                color,
+                texture,
+                metallic,
+                roughness,
+                specular,
+                sheen,
+                clearcoat,
+                clearcoat_roughness,
+                transmission,
+                subsurface,
+                ir,
+            } => {
+                let mut material = Principled::new(self.color_or_texture(color, texture)?);
+                if let Some(metallic) = metallic {
+                    material = material.with_metallic(self.scalar(metallic)?);
+                }
+                if let Some(roughness) = roughness {
+                    material = material.with_roughness(self.scalar(roughness)?);
+                }
+                if let Some(specular) = specular {
+                    material = material.with_specular(self.scalar(specular)?);
+                }
+                if let Some(sheen) = sheen {
+                    material = material.with_sheen(self.scalar(sheen)?);
+                }
+                if let Some(clearcoat) = clearcoat {
+                    material = material.with_clearcoat(self.scalar(clearcoat)?);
+                }
+                if let Some(roughness) = clearcoat_roughness {
+                    material = material.with_clearcoat_roughness(self.scalar(roughness)?);
+                }
+                if let Some(transmission) = transmission {
+                    material = material.with_transmission(self.scalar(transmission)?);
+                }
+                if let Some(subsurface) = subsurface {
+                    material = material.with_subsurface(self.scalar(subsurface)?);
+                }
+                if let Some(ir) = ir {
+                    material = material.with_ior(*ir);
+                }
+                Arc::new(material)
+            }
+            MaterialDesc::Mix {
+                first,
+                second,
+                weight,
+            } => Arc::new(MixMaterial::new(
+                named(first)?,
+                named(second)?,
+                self.scalar(weight)?,
+            )),
+            MaterialDesc::Coated {
+                base,
+                ir,
+                roughness,
+                tint,
+            } => Arc::new(
+                Coated::new(named(base)?, ir.unwrap_or(1.5), self.roughness(roughness)?)
+                    .with_tint(vec3(tint.unwrap_or([1.0, 1.0, 1.0]))),
+            ),
+            MaterialDesc::NormalMap {
+                material,
+                texture,
+                strength,
+            } => Arc::new(
+                NormalMap::new(named(material)?, self.data_texture(texture)?)
+                    .with_strength(strength.unwrap_or(1.0)),
+            ),
+            MaterialDesc::BumpMap {
+                material,
+                height,
+                scale,
+            } => Arc::new(BumpMap::new(
+                named(material)?,
+                self.data_texture(height)?,
+                *scale,
+            )),
+            MaterialDesc::DiffuseLight { color, texture } => {
+                Arc::new(DiffuseLight::new(self.color_or_texture(color, texture)?))
+            }
+            MaterialDesc::Isotropic { color, texture } => {
+                Arc::new(Isotropic::new(self.color_or_texture(color, texture)?))
+            }
+        })
+    }
+
+    fn object(
+        &self,
+        desc: &ObjectDesc,
+        materials: &BTreeMap<&str, Arc<dyn Material>>,
+        default_material: Option<Arc<dyn Material>>,
+    ) -> Result<Box<dyn Hitable>, String> {
+        let material = || match &desc.material {
+            Some(name) => materials
+                .get(name.as_str())
+                .cloned()
+                .ok_or_else(|| format!("unknown material `{}`", name)),
+            None => default_material
+                .clone()
+                .ok_or_else(|| String::from("missing `material`")),
+        };
+
+        let object: Box<dyn Hitable> = match &desc.shape {
+            ShapeDesc::Sphere { center, radius } => {
+                Box::new(Sphere::new(vec3(*center), *radius, material()?))
+            }
+            ShapeDesc::MovingSphere {
+                center0,
+                center1,
+                radius,
+                time0,
+                time1,
+            } => Box::new(MovingSphere::new(
+                *radius,
+                material()?,
+                vec3(*center0),
+                vec3(*center1),
+                *time0,
+                *time1,
+            )),
+            ShapeDesc::Plane {
+                orientation,
+                x0,
+                x1,
+                y0,
+                y1,
+                k,
+            } => {
+                let orientation = match orientation {
+                    OrientationDesc::XY => PlaneOrientation::XY,
+                    OrientationDesc::YZ => PlaneOrientation::YZ,
+                    OrientationDesc::ZX => PlaneOrientation::ZX,
+                };
+                Box::new(Plane::new(orientation, material()?, *x0, *x1, *y0, *y1, *k))
+            }
+            ShapeDesc::Cube { min, max } => {
+                Box::new(Cube::new(vec3(*min), vec3(*max), material()?))
+            }
+            ShapeDesc::Triangle { vertices, normals } => {
+                let [v0, v1, v2] = vertices.map(vec3);
+                match normals {
+                    Some(normals) => Box::new(Triangle::with_normals(
+                        v0,
+                        v1,
+                        v2,
+                        normals.map(vec3),
+                        material()?,
+                    )),
+                    None => Box::new(Triangle::new(v0, v1, v2, material()?)),
+                }
+            }
+            ShapeDesc::Obj { file } => {
+                let default_material = match desc.material {
+                    Some(_) => material()?,
+                    None => Arc::new(Principled::new(SolidColor::from_rbg(0.8, 0.8, 0.8))),
+                };
+                let path = self.base_dir.join(file);
+                let mesh =
+                    load_obj_with_sources(&path, default_material, &mut self.sources.borrow_mut())
+                        .map_err(|err| err.to_string())?;
+                Box::new(mesh)
+            }
+            ShapeDesc::ConstantMedium {
+                boundary,
+                density,
+                color,
+                texture,
+            } => {
+                if desc.material.is_some() {
+                    return Err(String::from(
+                        "a constant medium takes a `color` or `texture` instead of a `material`",
+                    ));
+                }
+                // The boundary is only used for its shape, so its material is optional
+                let boundary_material: Arc<dyn Material> = Arc::new(Dielectric::new(1.0));
+                let boundary = self.object(boundary, materials, Some(boundary_material))?;
+                let texture = self.color_or_texture(color, texture)?;
+                Box::new(ConstantMedium::new(boundary, texture, *density))
+            }
+            ShapeDesc::Instance { geometry } => {
+                let geometry = self
+                    .geometry
+                    .get(geometry)
+                    .cloned()
+                    .ok_or_else(|| format!("unknown geometry `{}`", geometry))?;
+                let matrix = matrix(&desc.transforms)?;
+                return Ok(match desc.material {
+                    Some(_) => Box::new(Instance::with_material(geometry, matrix, material()?)),
+                    None => Box::new(Instance::new(geometry, matrix)),
+                });
+            }
+        };
+
+        if desc.transforms.is_empty() {
+            return Ok(object);
+        }
+
+        Ok(Box::new(Transform::new(object, matrix(&desc.transforms)?)))
+    }
+}
+
+// Composed into a single matrix so the ray is only transformed once
+fn matrix(transforms: &[TransformDesc]) -> Result<Matrix4, String> {
+    let mut matrix = Matrix4::identity();
+    for transform in transforms {
+        let step = match transform {
+            TransformDesc::RotateY(angle) => Matrix4::rotation_y(*angle),
+            TransformDesc::Translate(offset) => Matrix4::translation(vec3(*offset)),
+            TransformDesc::Rotate { axis, angle } => {
+                if vec3(*axis).near_zero() {
+                    return Err(String::from("the rotation axis must not be zero"));
+                }
+                Matrix4::rotation(vec3(*axis), *angle)
+            }
+            TransformDesc::Scale(factors) => {
+                if factors.contains(&0.0) {
+                    return Err(String::from("scale factors must not be zero"));
+                }
+                Matrix4::scaling(vec3(*factors))
+            }
+        };
+        matrix = step * matrix;
+    }
+
+    Ok(matrix)
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// Names of `entries` ordered so the entries each one refers to come before it, whatever their
+// order in the file. An entry referring back to itself is an error at its line, names without an
+// entry are left for the builder to report
+fn build_order<'d, T>(
+    source: &str,
+    kind: &str,
+    entries: &'d BTreeMap<String, Spanned<T>>,
+    references: impl Fn(&'d T) -> Vec<&'d str>,
+) -> Result<Vec<&'d str>, SceneFileError> {
+    // Depth first, `path` holds the entries being visited
+    struct Search<'d, 's, T, F> {
+        source: &'s str,
+        kind: &'s str,
+        entries: &'d BTreeMap<String, Spanned<T>>,
+        references: F,
+        path: Vec<&'d str>,
+        order: Vec<&'d str>,
+    }
+
+    impl<'d, T, F: Fn(&'d T) -> Vec<&'d str>> Search<'d, '_, T, F> {
+        fn visit(&mut self, name: &'d str) -> Result<(), SceneFileError> {
+            let Some((name, entry)) = self.entries.get_key_value(name) else {
+                return Ok(());
+            };
+            if self.order.contains(&name.as_str()) {
+                return Ok(());
+            }
+            if let Some(start) = self.path.iter().position(|&other| other == name) {
+                let cycle: Vec<String> = self.path[start..]
+                    .iter()
+                    .chain([&name.as_str()])
+                    .map(|name| format!("`{}`", name))
+                    .collect();
+                return Err(error_at(
+                    line_of(self.source, entry.span().start),
+                    format!(
+                        "{} `{}` refers back to itself: {}",
+                        self.kind,
+                        name,
+                        cycle.join(" -> ")
+                    ),
+                ));
+            }
+
+            self.path.push(name);
+            for reference in (self.references)(entry.get_ref()) {
+                self.visit(reference)?;
+            }
+            self.path.pop();
+            self.order.push(name);
+
+            Ok(())
+        }
+    }
+
+    let mut search = Search {
+        source,
+        kind,
+        entries,
+        references,
+        path: Vec::new(),
+        order: Vec::new(),
+    };
+    for name in entries.keys() {
+        search.visit(name)?;
+    }
+
+    Ok(search.order)
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+// Rejects a `field` that is 0 or less, NaN included, as the command line does for its flags
+fn positive<T: PartialOrd + Default>(
+    source: &str,
+    value: &Spanned<T>,
+    field: &str,
+) -> Result<(), SceneFileError> {
+    if value.get_ref() > &T::default() {
+        Ok(())
+    } else {
+        Err(error_at(
+            line_of(source, value.span().start),
+            format!("`{}` must be greater than 0", field),
+        ))
+    }
+}
+
+fn error_at(line: usize, message: String) -> SceneFileError {
+    SceneFileError {
+        path: None,
+        line: Some(line),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = r#"
+[config]
+aspect_ratio = 1.0
+image_width = 16
+samples_per_pixel = 4
+max_depth = 8
+
+[camera]
+lookfrom = [0, 0, 5]
+lookat = [0, 0, 0]
+vup = [0, 1, 0]
+aperture = 0.0
+vfov = 40
+
+[materials.white]
+type = "lambertian"
+color = [0.7, 0.7, 0.7]
+"#;
+
+    // Line of the error parsing `body` after the header, and of `needle` in the same source
+    fn lines(body: &str, needle: &str) -> (Option<usize>, usize, String) {
+        let source = format!("{}{}", HEADER, body);
+        let err = parse_scene(&source, Path::new(".")).err().unwrap();
+        let expected = source.lines().position(|line| line.contains(needle));
+        (err.line, expected.unwrap() + 1, err.message)
+    }
+
+    #[test]
+    fn parses_the_header() {
+        let (_, world, _) = parse_scene(HEADER, Path::new(".")).unwrap();
+        assert!(world.is_empty());
+    }
+
+    #[test]
+    fn errors_point_at_their_line() {
+        let cases = [
+            (
+                "\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1\nmaterial = \"nope\"\n",
+                "[[objects]]",
+                "unknown material `nope`",
+            ),
+            (
+                "\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradius = = 1\n",
+                "radius = = 1",
+                "",
+            ),
+            (
+                "\n[materials.b]\ntype = \"coated\"\nbase = \"c\"\n\n[materials.c]\ntype = \"mix\"\nfirst = \"white\"\nsecond = \"b\"\nweight = 0.5\n",
+                "[materials.b]",
+                "material `b` refers back to itself: `b` -> `c` -> `b`",
+            ),
+        ];
+        for (body, needle, message) in cases {
+            let (line, expected, actual) = lines(body, needle);
+            assert_eq!(line, Some(expected), "{}", actual);
+            assert!(actual.contains(message), "{}", actual);
+        }
+
+        let source = HEADER.replace("samples_per_pixel = 4", "samples_per_pixel = 0");
+        let err = parse_scene(&source, Path::new(".")).err().unwrap();
+        assert_eq!(err.line, Some(5));
+        assert_eq!(err.message, "`samples_per_pixel` must be greater than 0");
+
+        let source = HEADER.replace("vfov = 40", "vfov = 40\nfocus = 2");
+        let err = parse_scene(&source, Path::new(".")).err().unwrap();
+        assert_eq!(err.line, Some(14));
+        assert!(err.message.contains("unknown field `focus`"), "{}", err);
+    }
+}
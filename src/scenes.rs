@@ -1,8 +1,87 @@
-use crate::{bvh::BVH, *};
+use crate::checkpoint::Fnv1a;
+use crate::*;
 use rand::Rng;
+use std::hash::Hasher;
 use std::sync::Arc;
 pub type Scene = (Config, World, Camera);
 
+pub struct SceneEntry {
+    pub name: &'static str,
+    pub build: fn() -> Scene,
+}
+
+impl SceneEntry {
+    // `build` with the name as the source of the scene, so checkpoints tell scenes apart
+    pub fn build_scene(&self) -> Scene {
+        let mut scene = (self.build)();
+        let mut hasher = Fnv1a::default();
+        hasher.write(self.name.as_bytes());
+        scene.0.source_hash = hasher.finish();
+
+        scene
+    }
+}
+
+// Every prebuilt scene, looked up by name from the command line
+pub const SCENES: &[SceneEntry] = &[
+    SceneEntry {
+        name: "get_random_spheres_scene",
+        build: get_random_spheres_scene,
+    },
+    SceneEntry {
+        name: "base_scene",
+        build: base_scene,
+    },
+    SceneEntry {
+        name: "base_scene_without_motion",
+        build: base_scene_without_motion,
+    },
+    SceneEntry {
+        name: "two_checkered_spheres",
+        build: two_checkered_spheres,
+    },
+    SceneEntry {
+        name: "two_perlin_spheres",
+        build: two_perlin_spheres,
+    },
+    SceneEntry {
+        name: "rect_light",
+        build: rect_light,
+    },
+    SceneEntry {
+        name: "earth_map_sphere",
+        build: earth_map_sphere,
+    },
+    SceneEntry {
+        name: "cornell_box_without_boxes",
+        build: cornell_box_without_boxes,
+    },
+    SceneEntry {
+        name: "cornell_box_scene",
+        build: cornell_box_scene,
+    },
+    SceneEntry {
+        name: "cornell_box_scene_with_smoke_boxes",
+        build: cornell_box_scene_with_smoke_boxes,
+    },
+    SceneEntry {
+        name: "ray_tracing_the_next_week",
+        build: ray_tracing_the_next_week,
+    },
+    SceneEntry {
+        name: "ray_tracing_the_next_week_full",
+        build: ray_tracing_the_next_week_full,
+    },
+    SceneEntry {
+        name: "dev_scene",
+        build: dev_scene,
+    },
+];
+
+pub fn find_scene(name: &str) -> Option<&'static SceneEntry> {
+    SCENES.iter().find(|scene| scene.name == name)
+}
+
 #[allow(dead_code)]
 pub fn get_random_spheres_scene() -> Scene {
     eprintln!("Random spheres scene!");
@@ -26,7 +105,7 @@ pub fn get_random_spheres_scene() -> Scene {
         background_color: None,
     });
 
-    let mut rng = rand::thread_rng();
+    let mut rng = Pcg32::new(0, 0);
     let mut world = World::new();
 
     let ground_mat = Arc::new(Lambertian::new(SolidColor::from_rbg(0.5, 0.5, 0.5)));
@@ -45,14 +124,14 @@ pub fn get_random_spheres_scene() -> Scene {
 
             if choose_mat < 0.8 {
                 // Diffuse
-                let albedo = Color::random(0.0..1.0) * Color::random(0.0..1.0);
+                let albedo = Color::random(0.0..1.0, &mut rng) * Color::random(0.0..1.0, &mut rng);
                 let sphere_mat = Arc::new(Lambertian::new(SolidColor::new(albedo)));
                 let sphere = Sphere::new(center, 0.2, sphere_mat);
 
                 world.push(Box::new(sphere));
             } else if choose_mat < 0.95 {
                 // Metal
-                let albedo = Color::random(0.4..1.0);
+                let albedo = Color::random(0.4..1.0, &mut rng);
                 let fuzz = rng.gen_range(0.0..0.5);
                 let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
                 let sphere = Sphere::new(center, 0.2, sphere_mat);
@@ -82,11 +161,10 @@ pub fn get_random_spheres_scene() -> Scene {
 
     let camera = Camera::new(&config.camera_config, config.aspect_ratio);
 
-    return (config, world, camera);
+    (config, world, camera)
 }
 
 #[allow(dead_code)]
-
 // add motion
 pub fn base_scene() -> Scene {
     eprintln!("Base Scene!");
@@ -126,11 +204,10 @@ pub fn base_scene() -> Scene {
     world.push(Box::new(sphere_left));
     world.push(Box::new(sphere_right));
 
-    return (config, world, camera);
+    (config, world, camera)
 }
 
 #[allow(dead_code)]
-
 pub fn base_scene_without_motion() -> Scene {
     eprintln!("Base Scene!");
     let camera_config = CameraConfig::new(CameraConfigOptions {
@@ -169,12 +246,12 @@ pub fn base_scene_without_motion() -> Scene {
     world.push(Box::new(sphere_left));
     world.push(Box::new(sphere_right));
 
-    return (config, world, camera);
+    (config, world, camera)
 }
 
 #[allow(dead_code)]
 pub fn two_checkered_spheres() -> Scene {
-    eprintln!("");
+    eprintln!();
     let camera_config = CameraConfig::new(CameraConfigOptions {
         lookfrom: Point3::new(13.0, 2.0, 3.0),
         lookat: Point3::new(0.0, 0.0, 0.0),
@@ -219,7 +296,7 @@ pub fn two_checkered_spheres() -> Scene {
     world.push(Box::new(sphere1));
     world.push(Box::new(sphere2));
 
-    return (config, world, camera);
+    (config, world, camera)
 }
 
 #[allow(dead_code)]
@@ -670,6 +747,16 @@ pub fn cornell_box_scene_with_smoke_boxes() -> Scene {
 
 #[allow(dead_code)]
 pub fn ray_tracing_the_next_week() -> Scene {
+    next_week(false)
+}
+
+// The final scene of the book with all of its objects
+#[allow(dead_code)]
+pub fn ray_tracing_the_next_week_full() -> Scene {
+    next_week(true)
+}
+
+fn next_week(full: bool) -> Scene {
     let camera_config = CameraConfig::new(CameraConfigOptions {
         lookfrom: Point3::new(478., 278., -600.),
         lookat: Point3::new(278.0, 278.0, 0.0),
@@ -692,29 +779,35 @@ pub fn ray_tracing_the_next_week() -> Scene {
 
     let mut world = World::new();
 
-    // let ground_mat = Arc::new(Lambertian::new(SolidColor::from_rbg(0.48, 0.83, 0.53)));
-    // let boxes_per_side = 20;
-    // let mut rng = rand::thread_rng();
-
-    // let mut boxes: HittableList = Vec::new();
-
-    // for i in 0..boxes_per_side {
-    //     for j in 0..boxes_per_side {
-    //         let w = 100.0;
-    //         let x0 = -1000.0 + (i as f64) * w;
-    //         let z0 = -1000.0 + (j as f64) * w;
-    //         let y0 = 0.0;
-    //         let x1 = x0 + w;
-    //         let y1 = rng.gen_range(1.0..101.0);
-    //         let z1 = z0 + w;
-    //         boxes.push(Box::new(Cube::new(
-    //             Point3::new(x0, y0, z0),
-    //             Point3::new(x1, y1, z1),
-    //             ground_mat.clone(),
-    //         )))
-    //     }
-    // }
-    // world.push(Box::new(BVH::new(boxes, 0.0, 1.0)));
+    let mut rng = Pcg32::new(0, 0);
+
+    // The ground of boxes, the Perlin sphere and the cluster of spheres of the book are left out
+    // by default
+    if full {
+        let ground_mat = Arc::new(Lambertian::new(SolidColor::from_rbg(0.48, 0.83, 0.53)));
+        let boxes_per_side = 20;
+
+        let mut boxes: World = Vec::new();
+
+        // Every box is the same unit cube, scaled and moved into place
+        let unit_cube: Arc<dyn Hitable> = Arc::new(Cube::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 1.0, 1.0),
+            ground_mat,
+        ));
+        for i in 0..boxes_per_side {
+            for j in 0..boxes_per_side {
+                let w = 100.0;
+                let x0 = -1000.0 + (i as f64) * w;
+                let z0 = -1000.0 + (j as f64) * w;
+                let y1 = rng.gen_range(1.0..101.0);
+                let matrix = Matrix4::translation(Vec3::new(x0, 0.0, z0))
+                    * Matrix4::scaling(Vec3::new(w, y1, w));
+                boxes.push(Box::new(Instance::new(unit_cube.clone(), matrix)))
+            }
+        }
+        world.push(Box::new(BVH::new(boxes, 0.0, 1.0)));
+    }
 
     let light_mat = Arc::new(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0)));
     world.push(Box::new(Plane::new(
@@ -786,30 +879,124 @@ pub fn ray_tracing_the_next_week() -> Scene {
         earth_surface,
     )));
 
-    // let perlin = Arc::new(Lambertian::new(NoiseTexture::new(0.1)));
-    // world.push(Box::new(Sphere::new(
-    //     Point3::new(220., 280., 300.),
-    //     80.0,
-    //     perlin,
-    // )));
+    if full {
+        let perlin = Arc::new(Lambertian::new(NoiseTexture::new(0.1)));
+        world.push(Box::new(Sphere::new(
+            Point3::new(220., 280., 300.),
+            80.0,
+            perlin,
+        )));
+
+        let mut boxes: World = Vec::new();
+        let white = Arc::new(Lambertian::new(SolidColor::from_rbg(0.73, 0.73, 0.73)));
+        let ns = 1000;
+        for _ in 0..ns {
+            boxes.push(Box::new(Sphere::new(
+                Point3::random(0.0..165.0, &mut rng),
+                10.0,
+                white.clone(),
+            )))
+        }
+
+        let boxes_r = RotateY::new(Box::new(BVH::new(boxes, 0.0, 1.0)), 15.0);
+        let boxes_t = Translate::new(Box::new(boxes_r), Vec3::new(-100., 270., 395.));
 
-    // let mut boxes: HittableList = Vec::new();
-    // let white = Arc::new(Lambertian::new(SolidColor::from_rbg(0.73, 0.73, 0.73)));
-    // let ns = 1000;
-    // for _ in 0..ns {
-    //     boxes.push(Box::new(Sphere::new(
-    //         Point3::random(0.0..165.0),
-    //         10.0,
-    //         white.clone(),
-    //     )))
-    // }
+        world.push(Box::new(boxes_t));
+    }
+
+    let camera = Camera::new(&config.camera_config, config.aspect_ratio);
+
+    (config, world, camera)
+}
 
-    // let boxes_r = RotateY::new(Box::new(BVH::new(boxes, 0.0, 1.0)), 15.0);
-    // let boxes_t = Translate::new(Box::new(boxes_r), Vec3::new(-100., 270., 395.));
+#[allow(dead_code)]
+pub fn dev_scene() -> Scene {
+    eprintln!("dev scene!");
+    let camera_config = CameraConfig::new(CameraConfigOptions {
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::new(0.0, 0.0, 0.0),
+        vup: Vec3::new(0.0, 1.0, 0.0),
+        aperture: 0.1,
+        vfov: 20.0,
+        dist_to_focus: Some(10.0),
+        time0: Some(0.0),
+        time1: Some(1.0),
+    });
 
-    // world.push(Box::new(boxes_t));
+    let config = Config::new(ConfigOptions {
+        aspect_ratio: 16.0 / 9.0,
+        image_width: 400,
+        samples_per_pixel: 50,
+        max_depth: 10,
+        camera_config,
+        background_color: Some(Color::new(0.0, 0.0, 0.0)),
+    });
 
     let camera = Camera::new(&config.camera_config, config.aspect_ratio);
+    let mut rng = Pcg32::new(0, 0);
+    let mut world = World::new();
+
+    let checkered_texture = CheckerTexture::new(
+        SolidColor::from_rbg(0.2, 0.3, 0.1),
+        SolidColor::from_rbg(0.9, 0.9, 0.9),
+    );
+
+    let ground_mat = Arc::new(Lambertian::new(checkered_texture));
+    let ground_sphere = Sphere::new(Point3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
+
+    world.push(Box::new(ground_sphere));
+
+    for a in -4..=4 {
+        for b in -4..=4 {
+            let choose_mat: f64 = rng.gen();
+            let center = Point3::new(
+                (a as f64) + rng.gen_range(0.0..0.9),
+                0.2,
+                (b as f64) + rng.gen_range(0.0..0.9),
+            );
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // Diffuse
+                    let albedo =
+                        Color::random(0.0..1.0, &mut rng) * Color::random(0.0..1.0, &mut rng);
+                    let sphere_mat = Arc::new(Lambertian::new(SolidColor::new(albedo)));
+
+                    let center0 = center;
+
+                    let center1 = center + Point3::new(0.0, rng.gen_range(0.0..0.5), 0.0);
+                    let sphere = MovingSphere::new(0.2, sphere_mat, center0, center1, 0.0, 1.0);
+
+                    world.push(Box::new(sphere));
+                } else if choose_mat < 0.95 {
+                    // Metal
+                    let albedo = Color::random(0.4..1.0, &mut rng);
+                    let fuzz = rng.gen_range(0.0..0.5);
+                    let sphere_mat = Arc::new(Metal::new(albedo, fuzz));
+                    let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                    world.push(Box::new(sphere));
+                } else {
+                    // Glass
+                    let sphere_mat = Arc::new(Dielectric::new(1.5));
+                    let sphere = Sphere::new(center, 0.2, sphere_mat);
+
+                    world.push(Box::new(sphere));
+                }
+            }
+        }
+    }
+
+    let mat1 = Arc::new(Dielectric::new(1.5));
+    let mat2 = Arc::new(Lambertian::new(SolidColor::from_rbg(0.4, 0.2, 0.1)));
+    let mat3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+
+    let sphere1 = Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1);
+    let sphere2 = Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2);
+    let sphere3 = Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3);
+
+    world.push(Box::new(sphere1));
+    world.push(Box::new(sphere2));
+    world.push(Box::new(sphere3));
 
     (config, world, camera)
 }
@@ -1,12 +1,11 @@
-use std::{
-    f64::consts::{FRAC_PI_2, PI},
-    sync::Arc,
-};
+use std::{f64::consts::PI, sync::Arc};
+
+use rand::RngCore;
 
 use crate::{
     hit::{HitRecord, Hitable},
     material::Material,
-    Point3, Vec3, AABB,
+    Onb, Point3, Ray, Sampler, Vec3, AABB,
 };
 
 pub struct Sphere {
@@ -32,6 +31,24 @@ impl Sphere {
 
         (u, v)
     }
+    // Derivatives of the point at `n` on the unit sphere along the u and v of `get_shpere_uv`,
+    // scaled to `radius`
+    fn tangents(n: Point3, radius: f64) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        let dpdu = (2.0 * PI * radius) * Vec3::new(n.z(), 0.0, -n.x());
+        // Undefined at the poles
+        if sin_theta < 1.0e-8 {
+            return (dpdu, Vec3::new(0.0, 0.0, 0.0));
+        }
+        let dpdv = (PI * radius)
+            * Vec3::new(
+                -n.x() * n.y() / sin_theta,
+                sin_theta,
+                -n.y() * n.z() / sin_theta,
+            );
+
+        (dpdu, dpdv)
+    }
     // fn get_shpere_uv(p: Point3) -> (f64, f64) {
     //     let theta = p.y().asin();
     //     let phi = p.z().atan2(p.x());
@@ -41,10 +58,9 @@ impl Sphere {
 
     //     (u, v)
     // }
-}
 
-impl Hitable for Sphere {
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    // Closest `t` in [t_min, t_max] where `r` crosses the surface
+    fn intersect(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
         let oc = r.origin() - self.center;
         let a = r.direction().length().powi(2);
         let half_b = r.direction().dot(oc);
@@ -63,6 +79,20 @@ impl Hitable for Sphere {
             }
         }
 
+        Some(root)
+    }
+}
+
+impl Hitable for Sphere {
+    fn hit(
+        &self,
+        r: &crate::Ray,
+        t_min: f64,
+        t_max: f64,
+        _: &mut dyn RngCore,
+    ) -> Option<HitRecord> {
+        let root = self.intersect(r, t_min, t_max)?;
+
         let mut rec = HitRecord {
             t: root,
             p: r.at(root),
@@ -71,6 +101,8 @@ impl Hitable for Sphere {
             front_face: false,
             u: 0.0,
             v: 0.0,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         };
         let outward_normal = (rec.p - self.center) / self.radius;
         let (u, v) = Sphere::get_shpere_uv(outward_normal);
@@ -78,6 +110,7 @@ impl Hitable for Sphere {
         rec.set_face_normal(r, outward_normal);
         rec.u = u;
         rec.v = v;
+        (rec.tangent, rec.bitangent) = Sphere::tangents(outward_normal, self.radius);
 
         Some(rec)
     }
@@ -88,6 +121,52 @@ impl Hitable for Sphere {
             self.center + radius_vec,
         ))
     }
+    fn is_light(&self) -> bool {
+        self.mat.is_emissive()
+    }
+    fn can_sample(&self) -> bool {
+        true
+    }
+    // Uniform over the cone of directions the sphere covers, 0 from inside the sphere
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self
+            .intersect(&Ray::new(origin, direction, 0.0), 0.001, f64::INFINITY)
+            .is_none()
+        {
+            return 0.0;
+        }
+
+        let distance_squared = (self.center - origin).length().powi(2);
+        if distance_squared <= self.radius.powi(2) {
+            return 0.0;
+        }
+        let cos_theta_max = (1.0 - self.radius.powi(2) / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let direction = self.center - origin;
+        let distance_squared = direction.length().powi(2);
+        let uvw = Onb::from_w(direction);
+
+        uvw.local(random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler.next_2d(),
+        ))
+    }
+}
+
+// Direction inside the cone around +z covering a sphere of `radius` at `distance_squared`
+fn random_to_sphere(radius: f64, distance_squared: f64, (r1, r2): (f64, f64)) -> Vec3 {
+    let cos_theta_max = (1.0 - radius.powi(2) / distance_squared).max(0.0).sqrt();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z.powi(2)).sqrt();
+
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
 }
 
 pub struct MovingSphere {
@@ -122,19 +201,16 @@ impl MovingSphere {
         self.center0
             + ((time - self.time0) / (self.time1 - self.time0)) * (self.center1 - self.center0)
     }
-    fn get_sphere_uv(p: Point3) -> (f64, f64) {
-        let theta = (-p.y()).acos();
-        let phi = (-p.y()).atan2(p.x()) + PI;
-
-        let u = phi / 2.0 * PI;
-        let v = theta / PI;
-
-        (u, v)
-    }
 }
 
 impl Hitable for MovingSphere {
-    fn hit(&self, r: &crate::Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(
+        &self,
+        r: &crate::Ray,
+        t_min: f64,
+        t_max: f64,
+        _: &mut dyn RngCore,
+    ) -> Option<HitRecord> {
         let oc = r.origin() - self.center(r.time());
         let a = r.direction().length().powi(2);
         let half_b = r.direction().dot(oc);
@@ -161,6 +237,8 @@ impl Hitable for MovingSphere {
             front_face: false,
             u: 0.0,
             v: 0.0,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            bitangent: Vec3::new(0.0, 0.0, 0.0),
         };
 
         let outward_normal = (rec.p - self.center(r.time())) / self.radius;
@@ -169,6 +247,7 @@ impl Hitable for MovingSphere {
         rec.set_face_normal(r, outward_normal);
         rec.u = u;
         rec.v = v;
+        (rec.tangent, rec.bitangent) = Sphere::tangents(outward_normal, self.radius);
 
         Some(rec)
     }
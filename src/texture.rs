@@ -1,9 +1,21 @@
+use std::path::Path;
+
+use image::ImageResult;
+
+use crate::tonemap::srgb_decode;
 use crate::{Color, Perlin, Point3};
 
 pub trait Texture: Send + Sync {
     fn value(&self, u: f64, v: f64, point: Point3) -> Color;
 }
 
+// Lets textures picked at runtime be used with the generic materials
+impl<T: Texture + ?Sized> Texture for Box<T> {
+    fn value(&self, u: f64, v: f64, point: Point3) -> Color {
+        (**self).value(u, v, point)
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct SolidColor {
     color_value: Color,
@@ -25,7 +37,7 @@ impl SolidColor {
 
 impl Texture for SolidColor {
     fn value(&self, _: f64, _: f64, _: Point3) -> Color {
-        return self.color_value;
+        self.color_value
     }
 }
 
@@ -78,6 +90,8 @@ pub struct ImageTexture {
     data: Vec<u8>,
     width: u32,
     height: u32,
+    // Whether the bytes are sRGB encoded colors rather than data like roughness or normals
+    srgb: bool,
 }
 
 impl ImageTexture {
@@ -86,20 +100,24 @@ impl ImageTexture {
             data,
             width,
             height,
+            srgb: true,
         }
     }
+    // Off for data maps, read as is instead of decoded from sRGB
+    pub fn with_srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
 
     pub fn from_file(file: &str) -> Self {
-        let img = image::open(file)
-            .expect("imgage for texture not found")
-            .to_rgb8();
+        Self::open(file).expect("imgage for texture not found")
+    }
+
+    pub fn open(file: impl AsRef<Path>) -> ImageResult<Self> {
+        let img = image::open(file)?.to_rgb8();
         let (w, h) = img.dimensions();
         let data = img.into_raw();
-        Self {
-            data,
-            width: w,
-            height: h,
-        }
+        Ok(Self::new(data, w, h))
     }
 }
 
@@ -116,9 +134,18 @@ impl Texture for ImageTexture {
             j = h - 1
         }
         let index = 3 * i + 3 * w * j;
-        let r = self.data[index] as f64 / 255.0;
-        let g = self.data[index + 1] as f64 / 255.0;
-        let b = self.data[index + 2] as f64 / 255.0;
-        Color::new(r, g, b)
+        let channel = |byte: u8| {
+            let value = byte as f64 / 255.0;
+            if self.srgb {
+                srgb_decode(value)
+            } else {
+                value
+            }
+        };
+        Color::new(
+            channel(self.data[index]),
+            channel(self.data[index + 1]),
+            channel(self.data[index + 2]),
+        )
     }
 }
@@ -0,0 +1,162 @@
+use std::str::FromStr;
+
+use crate::Color;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ToneMapper {
+    // Everything above 1 is cut off
+    #[default]
+    Clamp,
+    // L / (1 + L) on the luminance, keeps the hue
+    Reinhard,
+    // Stephen Hill's fit of the ACES reference and sRGB output transforms
+    Aces,
+    // Troy Sobotka's AgX, desaturates highlights towards white instead of skewing their hue
+    Agx,
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "aces" => Ok(ToneMapper::Aces),
+            "agx" => Ok(ToneMapper::Agx),
+            other => Err(format!(
+                "unknown tone mapper `{}`, expected clamp, reinhard, aces or agx",
+                other
+            )),
+        }
+    }
+}
+
+// Turns the linear radiance of the renderer into display colors for 8 bit formats
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ToneMapping {
+    pub operator: ToneMapper,
+    // In stops, every +1 doubles the brightness before the tone curve
+    pub exposure: f64,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapper, exposure: f64) -> Self {
+        Self { operator, exposure }
+    }
+
+    // Linear color in [0, 1]
+    pub fn apply(&self, color: Color) -> Color {
+        let c = self.exposure.exp2() * color;
+        let c = Color::new(c.x().max(0.0), c.y().max(0.0), c.z().max(0.0));
+        let mapped = match self.operator {
+            ToneMapper::Clamp => c,
+            ToneMapper::Reinhard => {
+                let luminance = luminance(c);
+                if luminance > 0.0 {
+                    (1.0 / (1.0 + luminance)) * c
+                } else {
+                    c
+                }
+            }
+            ToneMapper::Aces => aces(c),
+            ToneMapper::Agx => agx(c),
+        };
+
+        Color::new(
+            mapped.x().clamp(0.0, 1.0),
+            mapped.y().clamp(0.0, 1.0),
+            mapped.z().clamp(0.0, 1.0),
+        )
+    }
+    pub fn to_rgb8(&self, color: Color) -> [u8; 3] {
+        let c = self.apply(color);
+        let quantize = |v: f64| (255.0 * srgb_encode(v) + 0.5) as u8;
+        [quantize(c.x()), quantize(c.y()), quantize(c.z())]
+    }
+}
+
+// Rec. 709 luminance of a linear color
+pub(crate) fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+// sRGB transfer function, linear [0, 1] to the encoded value
+pub(crate) fn srgb_encode(v: f64) -> f64 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Inverse of `srgb_encode`
+pub(crate) fn srgb_decode(v: f64) -> f64 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn mul(m: &[[f64; 3]; 3], c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.x() + m[0][1] * c.y() + m[0][2] * c.z(),
+        m[1][0] * c.x() + m[1][1] * c.y() + m[1][2] * c.z(),
+        m[2][0] * c.x() + m[2][1] * c.y() + m[2][2] * c.z(),
+    )
+}
+
+fn per_channel(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+fn aces(c: Color) -> Color {
+    // sRGB to the ACES working space with the RRT saturation, and back to linear sRGB
+    const INPUT: [[f64; 3]; 3] = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    const OUTPUT: [[f64; 3]; 3] = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+    let fit = |v: f64| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    };
+
+    mul(&OUTPUT, per_channel(mul(&INPUT, c), fit))
+}
+
+// Polynomial fit of the default AgX look from Benjamin Wrensch's minimal implementation
+fn agx(c: Color) -> Color {
+    const INSET: [[f64; 3]; 3] = [
+        [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+        [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+        [0.0423756549057051, 0.0784336, 0.879142973793104],
+    ];
+    const OUTSET: [[f64; 3]; 3] = [
+        [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+        [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+        [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+    ];
+    const MIN_EV: f64 = -12.47393;
+    const MAX_EV: f64 = 4.026069;
+    let contrast = |v: f64| {
+        let encoded = (v.max(1.0e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+        let x2 = encoded * encoded;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * encoded + 31.96 * x4 - 6.868 * x2 * encoded
+            + 0.4298 * x2
+            + 0.1191 * encoded
+            - 0.00232
+    };
+
+    // The curve gives display encoded values, decoded with a 2.2 gamma so they go through
+    // the same sRGB encoding as the other operators
+    let display = mul(&OUTSET, per_channel(mul(&INSET, c), contrast));
+    per_channel(display, |v| v.max(0.0).powf(2.2))
+}
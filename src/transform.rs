@@ -0,0 +1,357 @@
+use std::ops::Mul;
+
+use rand::RngCore;
+
+use crate::{HitRecord, Hitable, Point3, Ray, Sampler, Vec3, AABB};
+
+// Row major affine matrix, points are column vectors so `a * b` applies `b` first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+    pub fn identity() -> Self {
+        Self::scaling(Vec3::new(1.0, 1.0, 1.0))
+    }
+    pub fn translation(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x()],
+            [0.0, 1.0, 0.0, offset.y()],
+            [0.0, 0.0, 1.0, offset.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn scaling(factors: Vec3) -> Self {
+        Self::new([
+            [factors.x(), 0.0, 0.0, 0.0],
+            [0.0, factors.y(), 0.0, 0.0],
+            [0.0, 0.0, factors.z(), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    // Counter clockwise by `angle` degrees looking down `axis`
+    pub fn rotation(axis: Vec3, angle: f64) -> Self {
+        let a = axis.normalized();
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x(), a.y(), a.z());
+
+        Self::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+    pub fn rotation_x(angle: f64) -> Self {
+        Self::rotation(Vec3::new(1.0, 0.0, 0.0), angle)
+    }
+    pub fn rotation_y(angle: f64) -> Self {
+        Self::rotation(Vec3::new(0.0, 1.0, 0.0), angle)
+    }
+    pub fn rotation_z(angle: f64) -> Self {
+        Self::rotation(Vec3::new(0.0, 0.0, 1.0), angle)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Self::new(m)
+    }
+    // Gauss-Jordan elimination with partial pivoting, None for a singular matrix
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap();
+            if a[pivot][col].abs() < 1.0e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for j in 0..4 {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+
+        Some(Self::new(inv))
+    }
+    // Rotation and translation only, which keep lengths and solid angles
+    pub fn is_rigid(&self) -> bool {
+        const EPS: f64 = 1.0e-9;
+        (0..3).all(|i| {
+            (0..3).all(|j| {
+                let dot: f64 = (0..3).map(|k| self.m[k][i] * self.m[k][j]).sum();
+                let expected = if i == j { 1.0 } else { 0.0 };
+                (dot - expected).abs() < EPS
+            })
+        })
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+    // Ignores the translation
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+    // Multiplies by the transpose, so on the inverse of a matrix this maps normals, which have to
+    // stay perpendicular to a scaled surface
+    pub fn transform_normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * n.x() + m[1][0] * n.y() + m[2][0] * n.z(),
+            m[0][1] * n.x() + m[1][1] * n.y() + m[2][1] * n.z(),
+            m[0][2] * n.x() + m[1][2] * n.y() + m[2][2] * n.z(),
+        )
+    }
+    // Bounding box of the transformed corners of `bbox`
+    pub fn transform_box(&self, bbox: &AABB) -> AABB {
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for corner in 0..8 {
+            let pick = |axis: usize| {
+                if corner & (1 << axis) == 0 {
+                    bbox.min()[axis]
+                } else {
+                    bbox.max()[axis]
+                }
+            };
+            let p = self.transform_point(Point3::new(pick(0), pick(1), pick(2)));
+            for c in 0..3 {
+                min[c] = min[c].min(p[c]);
+                max[c] = max[c].max(p[c]);
+            }
+        }
+
+        AABB::new(min, max)
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4::new(m)
+    }
+}
+
+// Object to world matrix with everything derived from it that's needed per ray
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Affine {
+    matrix: Matrix4,
+    inverse: Matrix4,
+    rigid: bool,
+}
+
+impl Affine {
+    pub(crate) fn new(matrix: Matrix4) -> Self {
+        let inverse = matrix
+            .inverse()
+            .expect("[Transform::new] The matrix is not invertible");
+
+        Self {
+            matrix,
+            inverse,
+            rigid: matrix.is_rigid(),
+        }
+    }
+    pub(crate) fn matrix(&self) -> Matrix4 {
+        self.matrix
+    }
+    pub(crate) fn is_rigid(&self) -> bool {
+        self.rigid
+    }
+
+    // The direction isn't normalized, so `t` is the same in both spaces
+    pub(crate) fn hit(
+        &self,
+        obj: &dyn Hitable,
+        r: &Ray,
+        t_min: f64,
+        t_max: f64,
+        rng: &mut dyn RngCore,
+    ) -> Option<HitRecord> {
+        let object_ray = Ray::new(
+            self.inverse.transform_point(r.origin()),
+            self.inverse.transform_vector(r.direction()),
+            r.time(),
+        );
+        let mut rec = obj.hit(&object_ray, t_min, t_max, rng)?;
+
+        // A linear map keeps which side of the surface the ray is on, so `front_face` stays
+        rec.p = self.matrix.transform_point(rec.p);
+        rec.normal = self.inverse.transform_normal(rec.normal).normalized();
+        rec.tangent = self.matrix.transform_vector(rec.tangent);
+        rec.bitangent = self.matrix.transform_vector(rec.bitangent);
+
+        Some(rec)
+    }
+    pub(crate) fn bounding_box(&self, obj: &dyn Hitable, time0: f64, time1: f64) -> Option<AABB> {
+        Some(self.matrix.transform_box(&obj.bounding_box(time0, time1)?))
+    }
+    // Solid angle densities only carry over under rigid transforms, scaled lights are left to
+    // material sampling
+    pub(crate) fn is_light(&self, obj: &dyn Hitable) -> bool {
+        self.is_rigid() && obj.is_light()
+    }
+    pub(crate) fn can_sample(&self, obj: &dyn Hitable) -> bool {
+        self.is_rigid() && obj.can_sample()
+    }
+    pub(crate) fn pdf_value(&self, obj: &dyn Hitable, origin: Point3, direction: Vec3) -> f64 {
+        obj.pdf_value(
+            self.inverse.transform_point(origin),
+            self.inverse.transform_vector(direction),
+        )
+    }
+    pub(crate) fn random(
+        &self,
+        obj: &dyn Hitable,
+        origin: Point3,
+        sampler: &mut dyn Sampler,
+    ) -> Vec3 {
+        self.matrix
+            .transform_vector(obj.random(self.inverse.transform_point(origin), sampler))
+    }
+}
+
+// Places an object with an arbitrary affine matrix: any mix of translation, rotation about any
+// axis and non-uniform scaling
+pub struct Transform {
+    obj: Box<dyn Hitable>,
+    affine: Affine,
+}
+
+impl Transform {
+    pub fn new(obj: Box<dyn Hitable>, matrix: Matrix4) -> Self {
+        Self {
+            obj,
+            affine: Affine::new(matrix),
+        }
+    }
+    pub fn matrix(&self) -> Matrix4 {
+        self.affine.matrix()
+    }
+}
+
+impl Hitable for Transform {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        self.affine.hit(self.obj.as_ref(), r, t_min, t_max, rng)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.affine.bounding_box(self.obj.as_ref(), time0, time1)
+    }
+    fn is_light(&self) -> bool {
+        self.affine.is_light(self.obj.as_ref())
+    }
+    fn can_sample(&self) -> bool {
+        self.affine.can_sample(self.obj.as_ref())
+    }
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.affine.pdf_value(self.obj.as_ref(), origin, direction)
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.affine.random(self.obj.as_ref(), origin, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheared() -> Matrix4 {
+        Matrix4::translation(Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation(Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix4::scaling(Vec3::new(2.0, 0.5, 3.0))
+            * Matrix4::rotation_z(45.0)
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = sheared();
+        let inverse = m.inverse().unwrap();
+        for product in [inverse * m, m * inverse] {
+            for i in 0..4 {
+                for j in 0..4 {
+                    let expected = if i == j { 1.0 } else { 0.0 };
+                    assert!(
+                        (product.m[i][j] - expected).abs() < 1.0e-12,
+                        "{:?}",
+                        product
+                    );
+                }
+            }
+        }
+        assert!(Matrix4::scaling(Vec3::new(1.0, 0.0, 1.0))
+            .inverse()
+            .is_none());
+    }
+
+    #[test]
+    fn normals_stay_perpendicular() {
+        let m = sheared();
+        let inverse = m.inverse().unwrap();
+        let normal = Vec3::new(1.0, 2.0, -1.0);
+        let (a, b) = (Vec3::new(2.0, -1.0, 0.0), Vec3::new(1.0, 0.0, 1.0));
+        let transformed = inverse.transform_normal(normal);
+        for tangent in [a, b] {
+            assert!(tangent.dot(normal).abs() < 1.0e-12);
+            let dot = m.transform_vector(tangent).dot(transformed);
+            assert!(dot.abs() < 1.0e-12, "{}", dot);
+        }
+    }
+}
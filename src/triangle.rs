@@ -0,0 +1,317 @@
+use std::sync::Arc;
+
+use rand::RngCore;
+
+use crate::{HitRecord, Hitable, Material, Point3, Ray, Vec3, AABB, BVH};
+
+// Möller-Trumbore, returns t and the barycentric coordinates of v1 and v2
+fn intersect(vertices: &[Point3; 3], r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    const EPS: f64 = 1.0e-12;
+    let e1 = vertices[1] - vertices[0];
+    let e2 = vertices[2] - vertices[0];
+
+    let pvec = r.direction().cross(e2);
+    let det = e1.dot(pvec);
+    if det.abs() < EPS {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - vertices[0];
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(e1);
+    let b2 = r.direction().dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+
+    Some((t, b1, b2))
+}
+
+fn interpolate(values: [Vec3; 3], b1: f64, b2: f64) -> Vec3 {
+    (1.0 - b1 - b2) * values[0] + b1 * values[1] + b2 * values[2]
+}
+
+fn bounding_box(vertices: &[Point3; 3]) -> AABB {
+    // Padded like Plane so triangles lying in an axis plane still have some thickness
+    const PAD: f64 = 0.0001;
+    let mut min = vertices[0];
+    let mut max = vertices[0];
+    for v in &vertices[1..] {
+        for c in 0..3 {
+            min[c] = min[c].min(v[c]);
+            max[c] = max[c].max(v[c]);
+        }
+    }
+    let pad = Vec3::new(PAD, PAD, PAD);
+
+    AABB::new(min - pad, max + pad)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn hit_record(
+    r: &Ray,
+    t: f64,
+    b1: f64,
+    b2: f64,
+    vertices: &[Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    mat: &Arc<dyn Material>,
+) -> HitRecord {
+    let e1 = vertices[1] - vertices[0];
+    let e2 = vertices[2] - vertices[0];
+    let (u, v) = match uvs {
+        Some(uvs) => {
+            let uv = interpolate(
+                [
+                    Vec3::new(uvs[0].0, uvs[0].1, 0.0),
+                    Vec3::new(uvs[1].0, uvs[1].1, 0.0),
+                    Vec3::new(uvs[2].0, uvs[2].1, 0.0),
+                ],
+                b1,
+                b2,
+            );
+            (uv.x(), uv.y())
+        }
+        None => (b1, b2),
+    };
+    // Solves the edges for the derivatives along the texture coordinates, which are the
+    // barycentric coordinates when the triangle has no uvs
+    let (tangent, bitangent) = match uvs {
+        Some(uvs) => {
+            let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+            let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < 1.0e-12 {
+                (Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 0.0))
+            } else {
+                ((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det)
+            }
+        }
+        None => (e1, e2),
+    };
+
+    let mut rec = HitRecord {
+        p: r.at(t),
+        t,
+        mat: mat.clone(),
+        normal: Vec3::new(0.0, 0.0, 0.0),
+        front_face: false,
+        u,
+        v,
+        tangent,
+        bitangent,
+    };
+
+    // The side is decided by the geometric normal, the shading normal is flipped to match it
+    let geometric_normal = e1.cross(e2).normalized();
+    rec.set_face_normal(r, geometric_normal);
+    if let Some(normals) = normals {
+        let shading_normal = interpolate(normals, b1, b2).normalized();
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            (-1.0) * shading_normal
+        };
+    }
+
+    rec
+}
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    mat: Arc<dyn Material>,
+}
+
+impl Triangle {
+    // Counter clockwise vertices face the viewer, (u, v) are the barycentric coordinates
+    pub fn new(v0: Point3, v1: Point3, v2: Point3, mat: Arc<dyn Material>) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: None,
+            mat,
+        }
+    }
+    // Shading normals interpolated across the triangle
+    pub fn with_normals(
+        v0: Point3,
+        v1: Point3,
+        v2: Point3,
+        normals: [Vec3; 3],
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            vertices: [v0, v1, v2],
+            normals: Some(normals),
+            mat,
+        }
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _: &mut dyn RngCore) -> Option<HitRecord> {
+        let (t, b1, b2) = intersect(&self.vertices, r, t_min, t_max)?;
+
+        Some(hit_record(
+            r,
+            t,
+            b1,
+            b2,
+            &self.vertices,
+            self.normals,
+            None,
+            &self.mat,
+        ))
+    }
+    fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
+        Some(bounding_box(&self.vertices))
+    }
+}
+
+// Indices into the buffers of a MeshData
+#[derive(Clone, Copy, Debug)]
+pub struct MeshFace {
+    pub vertices: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub faces: Vec<MeshFace>,
+    pub materials: Vec<Arc<dyn Material>>,
+}
+
+// A face of a mesh, looking its vertices up in the shared buffers
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [Point3; 3] {
+        let face = &self.mesh.faces[self.face];
+        face.vertices.map(|i| self.mesh.positions[i])
+    }
+}
+
+impl Hitable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, _: &mut dyn RngCore) -> Option<HitRecord> {
+        let vertices = self.vertices();
+        let (t, b1, b2) = intersect(&vertices, r, t_min, t_max)?;
+
+        let mesh = &self.mesh;
+        let face = &mesh.faces[self.face];
+        Some(hit_record(
+            r,
+            t,
+            b1,
+            b2,
+            &vertices,
+            face.normals.map(|n| n.map(|i| mesh.normals[i])),
+            face.uvs.map(|uv| uv.map(|i| mesh.uvs[i])),
+            &mesh.materials[face.material],
+        ))
+    }
+    fn bounding_box(&self, _: f64, _: f64) -> Option<AABB> {
+        Some(bounding_box(&self.vertices()))
+    }
+}
+
+pub struct TriangleMesh {
+    // None for a mesh without faces
+    bvh: Option<BVH>,
+    mesh: Arc<MeshData>,
+}
+
+impl TriangleMesh {
+    pub fn new(mesh: MeshData) -> Self {
+        let mesh = Arc::new(mesh);
+        let triangles: Vec<Box<dyn Hitable>> = (0..mesh.faces.len())
+            .map(|face| {
+                Box::new(MeshTriangle {
+                    mesh: mesh.clone(),
+                    face,
+                }) as Box<dyn Hitable>
+            })
+            .collect();
+        let bvh = if triangles.is_empty() {
+            None
+        } else {
+            Some(BVH::new(triangles, 0.0, 1.0))
+        };
+
+        Self { bvh, mesh }
+    }
+    pub fn mesh(&self) -> &MeshData {
+        &self.mesh
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
+        self.bvh.as_ref()?.hit(r, t_min, t_max, rng)
+    }
+    fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
+        self.bvh.as_ref()?.bounding_box(time0, time1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertices() -> [Point3; 3] {
+        [
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(3.0, 0.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+        ]
+    }
+
+    fn ray(from: Point3, to: Point3) -> Ray {
+        Ray::new(from, to - from, 0.0)
+    }
+
+    #[test]
+    fn intersects_with_barycentrics() {
+        let target = interpolate(vertices(), 0.25, 0.5);
+        for from in [Point3::new(0.0, 0.0, 2.0), Point3::new(4.0, 1.0, -3.0)] {
+            let (t, b1, b2) = intersect(&vertices(), &ray(from, target), 0.001, 10.0).unwrap();
+            assert!((t - 1.0).abs() < 1.0e-12, "t = {}", t);
+            assert!((b1 - 0.25).abs() < 1.0e-12 && (b2 - 0.5).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn misses_outside_the_edges_and_range() {
+        let from = Point3::new(0.0, 0.0, 2.0);
+        for target in [
+            Point3::new(0.9, 0.5, 0.0),
+            Point3::new(2.0, -0.1, 0.0),
+            Point3::new(2.1, 1.0, 0.0),
+        ] {
+            assert!(intersect(&vertices(), &ray(from, target), 0.001, 10.0).is_none());
+        }
+        let inside = ray(from, Point3::new(1.5, 0.5, 0.0));
+        assert!(intersect(&vertices(), &inside, 1.001, 10.0).is_none());
+        assert!(intersect(&vertices(), &inside, 0.001, 0.999).is_none());
+        let parallel = Ray::new(from, Vec3::new(1.0, 1.0, 0.0), 0.0);
+        assert!(intersect(&vertices(), &parallel, 0.001, 10.0).is_none());
+    }
+}
@@ -3,7 +3,9 @@ use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Range, Sub, SubAssign,
 };
 
-use rand::Rng;
+use rand::{Rng, RngCore};
+
+use crate::ToneMapping;
 
 #[derive(Clone, Copy)]
 pub struct Vec3 {
@@ -50,23 +52,14 @@ impl Vec3 {
         self / self.length()
     }
     pub fn format_color(self, samples_per_pixel: u64) -> String {
-        let ir = (256.0
-            * (self[0] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
-        let ig = (256.0
-            * (self[1] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
-        let ib = (256.0
-            * (self[2] / (samples_per_pixel as f64))
-                .sqrt()
-                .clamp(0.0, 0.999)) as u64;
+        let [ir, ig, ib] = (self / (samples_per_pixel as f64)).to_rgb8();
         format!("{} {} {}", ir, ig, ib)
     }
-    pub fn random(r: Range<f64>) -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    // Clamped and sRGB encoded 8 bit color
+    pub fn to_rgb8(self) -> [u8; 3] {
+        ToneMapping::default().to_rgb8(self)
+    }
+    pub fn random(r: Range<f64>, rng: &mut dyn RngCore) -> Vec3 {
         Vec3 {
             e: [
                 rng.gen_range(r.clone()),
@@ -75,16 +68,16 @@ impl Vec3 {
             ],
         }
     }
-    pub fn random_in_unit_sphere() -> Vec3 {
+    pub fn random_in_unit_sphere(rng: &mut dyn RngCore) -> Vec3 {
         loop {
-            let v = Vec3::random(-1.0..1.0);
+            let v = Vec3::random(-1.0..1.0, rng);
             if v.length() < 1.0 {
                 return v;
             }
         }
     }
-    pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
-        let in_unit_sphere = Self::random_in_unit_sphere();
+    pub fn random_in_hemisphere(normal: Vec3, rng: &mut dyn RngCore) -> Vec3 {
+        let in_unit_sphere = Self::random_in_unit_sphere(rng);
         if in_unit_sphere.dot(normal) > 0.0 {
             // In the same hemisphere as the normal
             in_unit_sphere
@@ -92,6 +85,36 @@ impl Vec3 {
             (-1.0) * in_unit_sphere
         }
     }
+    // The `(u, v)` mappings below turn a uniform point of the unit square into the named
+    // distribution, keeping the stratification of sampler points
+    //
+    // Cosine distributed around +z
+    pub fn cosine_direction((r1, r2): (f64, f64)) -> Vec3 {
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        let z = (1.0 - r2).sqrt();
+        Vec3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), z)
+    }
+    // Uniform on the unit sphere
+    pub fn uniform_sphere((r1, r2): (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * r2;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * r1;
+        Vec3::new(phi.cos() * r, phi.sin() * r, z)
+    }
+    // Uniform in the unit disk in the xy plane (Shirley-Chiu concentric mapping)
+    pub fn concentric_disk((r1, r2): (f64, f64)) -> Vec3 {
+        let (a, b) = (2.0 * r1 - 1.0, 2.0 * r2 - 1.0);
+        if a == 0.0 && b == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let quarter = std::f64::consts::FRAC_PI_4;
+        let (r, theta) = if a.abs() > b.abs() {
+            (a, quarter * (b / a))
+        } else {
+            (b, 2.0 * quarter - quarter * (a / b))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
     pub fn near_zero(self) -> bool {
         const EPS: f64 = 1.0e-8;
         self[0].abs() < EPS && self[1].abs() < EPS && self[2].abs() < EPS
@@ -105,9 +128,7 @@ impl Vec3 {
         let r_out_parallel = -(1.0 - r_out_perp.length().powi(2)).abs().sqrt() * n;
         r_out_perp + r_out_parallel
     }
-    pub fn random_in_unit_disk() -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    pub fn random_in_unit_disk(rng: &mut dyn RngCore) -> Vec3 {
         loop {
             let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
             if p.length() < 1.0 {
@@ -158,7 +179,7 @@ impl Sub for Vec3 {
 }
 
 impl SubAssign for Vec3 {
-    fn sub_assign(&mut self, rhs: Vec3) -> () {
+    fn sub_assign(&mut self, rhs: Vec3) {
         *self = Vec3 {
             e: [self[0] - rhs[0], self[1] - rhs[1], self[2] - rhs[2]],
         };
@@ -176,7 +197,7 @@ impl Mul<f64> for Vec3 {
 }
 
 impl MulAssign<f64> for Vec3 {
-    fn mul_assign(&mut self, rhs: f64) -> () {
+    fn mul_assign(&mut self, rhs: f64) {
         *self = Vec3 {
             e: [self[0] * rhs, self[1] * rhs, self[2] * rhs],
         };
@@ -213,7 +234,7 @@ impl Div<f64> for Vec3 {
 }
 
 impl DivAssign<f64> for Vec3 {
-    fn div_assign(&mut self, rhs: f64) -> () {
+    fn div_assign(&mut self, rhs: f64) {
         *self = Vec3 {
             e: [self[0] / rhs, self[1] / rhs, self[2] / rhs],
         };
@@ -1,15 +1,17 @@
-use crate::{HitRecord, Hitable, Ray, AABB};
+use rand::RngCore;
+
+use crate::{HitRecord, Hitable, Point3, Ray, Sampler, Vec3, AABB};
 
 pub type World = Vec<Box<dyn Hitable>>;
 pub type HittableList = World;
 
 impl Hitable for World {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64, rng: &mut dyn RngCore) -> Option<HitRecord> {
         let mut tmp_rec = None;
         let mut closest_so_far = t_max;
 
         for object in self {
-            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+            if let Some(rec) = object.hit(r, t_min, closest_so_far, rng) {
                 closest_so_far = rec.t;
                 tmp_rec = Some(rec);
             }
@@ -19,11 +21,29 @@ impl Hitable for World {
     }
     fn bounding_box(&self, time0: f64, time1: f64) -> Option<AABB> {
         let bbox = self.first()?.bounding_box(time0, time1)?;
-        let output_box = self.into_iter().skip(1).try_fold(bbox, |acc, obj| {
+        self.iter().skip(1).try_fold(bbox, |acc, obj| {
             let curr_bound = obj.bounding_box(time0, time1)?;
             Some(AABB::surrounding_box(&acc, &curr_bound))
-        });
+        })
+    }
+    // Mixture of the objects, each picked with the same probability
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .iter()
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+
+        sum / self.len() as f64
+    }
+    fn random(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        if self.is_empty() {
+            return Vec3::new(1.0, 0.0, 0.0);
+        }
+        let index = sampler.next_index(self.len());
 
-        output_box
+        self[index].random(origin, sampler)
     }
 }